@@ -0,0 +1,435 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::process::Command;
+use crate::api::{http_client, ApiMultirateResponse};
+use crate::config::{get_config_dir, read_config};
+use crate::engine::{derive_rate, table_contains, RATE_DISPLAY_DECIMALS};
+
+// Alerts are stored in alerts.json in the config directory and checked every time a new table of rates is fetched.
+// A triggered alert is printed to stderr, so it doesn't end up in JSON, CSV or batch output, and also sent to the configured command and webhook.
+// "above" and "below" alerts trigger when the rate crosses the threshold, and again only after it has crossed back.
+// "change" alerts trigger when the rate has moved by the percentage since the reference rate, which is then reset to the current rate.
+
+#[derive(Error, Debug)]
+enum AlertError {
+    #[error("Invalid currency pair. Use two currency codes, e.g. EURUSD or EUR/USD.")]
+    InvalidPair,
+    #[error("Invalid alert condition. Use above RATE, below RATE or change PERCENT%.")]
+    InvalidCondition,
+    #[error("No alert with id {0}.")]
+    NotFound(u32),
+    #[error("Error reading alerts.")]
+    ReadError,
+    #[error("Error writing alerts.")]
+    WriteError,
+    #[error("Alert command failed with {0}.")]
+    CommandFailed(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertCondition {
+    Above(Decimal),
+    Below(Decimal),
+    // Percentage, e.g. 2 for 2%.
+    Change(Decimal),
+}
+
+impl fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertCondition::Above(rate) => write!(f, "above {}", rate),
+            AlertCondition::Below(rate) => write!(f, "below {}", rate),
+            AlertCondition::Change(percent) => write!(f, "change {}%", percent),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Alert {
+    pub id: u32,
+    pub from: String,
+    pub to: String,
+    pub condition: AlertCondition,
+    // Rate a change is measured from, set when the alert is first checked if it wasn't known when it was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_rate: Option<Decimal>,
+    // Whether the rate was above/below the threshold at the last check.
+    #[serde(default)]
+    pub met: bool,
+}
+
+pub struct TriggeredAlert {
+    pub alert: Alert,
+    pub rate: Decimal,
+    // For change alerts, the reference rate the change was measured from.
+    pub previous_rate: Option<Decimal>,
+    pub time_last_update_utc: String,
+}
+
+impl TriggeredAlert {
+    pub fn message(&self) -> String {
+        let alert = &self.alert;
+        let rate = self.rate.round_dp(RATE_DISPLAY_DECIMALS).normalize();
+        match (&alert.condition, self.previous_rate) {
+            (AlertCondition::Change(_), Some(previous_rate)) => {
+                let percent = ((self.rate - previous_rate) * Decimal::ONE_HUNDRED / previous_rate).round_dp(2).normalize();
+                let sign = if percent.is_sign_positive() { "+" } else { "" };
+                format!("Alert {}: {}/{} changed by {}{}% from {} to {}.", alert.id, alert.from, alert.to, sign, percent, previous_rate.round_dp(RATE_DISPLAY_DECIMALS).normalize(), rate)
+            }
+            (condition, _) => format!("Alert {}: {}/{} is {} ({}).", alert.id, alert.from, alert.to, condition, rate),
+        }
+    }
+}
+
+// Accepts EURUSD, EUR/USD and EUR-USD.
+pub fn parse_pair(pair: &str) -> Result<(String, String)> {
+    let pair = pair.to_uppercase();
+    let codes = match pair.split_once(['/', '-']) {
+        Some((from, to)) => (from.to_string(), to.to_string()),
+        None if pair.len() == 6 && pair.is_ascii() => (pair[..3].to_string(), pair[3..].to_string()),
+        None => return Err(AlertError::InvalidPair.into()),
+    };
+    if codes.0.len() != 3 || codes.1.len() != 3 || !codes.0.chars().chain(codes.1.chars()).all(|c| c.is_ascii_alphabetic()) {
+        return Err(AlertError::InvalidPair.into());
+    }
+    Ok(codes)
+}
+
+pub fn parse_condition(kind: &str, value: &str) -> Result<AlertCondition> {
+    let number = match Decimal::from_str(value.trim_end_matches('%')) {
+        Ok(number) if number.is_sign_positive() && !number.is_zero() => number,
+        _ => return Err(AlertError::InvalidCondition.into()),
+    };
+    match kind {
+        "above" if !value.ends_with('%') => Ok(AlertCondition::Above(number)),
+        "below" if !value.ends_with('%') => Ok(AlertCondition::Below(number)),
+        "change" => Ok(AlertCondition::Change(number)),
+        _ => Err(AlertError::InvalidCondition.into()),
+    }
+}
+
+pub fn alerts_path() -> Result<PathBuf> {
+    Ok(PathBuf::from(get_config_dir()?).join("alerts.json"))
+}
+
+pub fn read_alerts(path: &Path) -> Result<Vec<Alert>> {
+    if fs::metadata(path).is_err() {
+        return Ok(Vec::new());
+    }
+    let alerts_json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(_) => return Err(AlertError::ReadError.into()),
+    };
+    match serde_json::from_str(&alerts_json) {
+        Ok(alerts) => Ok(alerts),
+        Err(_) => Err(AlertError::ReadError.into()),
+    }
+}
+
+pub fn write_alerts(path: &Path, alerts: &[Alert]) -> Result<()> {
+    let alerts_json = match serde_json::to_string_pretty(alerts) {
+        Ok(json) => json,
+        Err(_) => return Err(AlertError::WriteError.into()),
+    };
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return Err(AlertError::WriteError.into());
+        }
+    }
+    match fs::write(path, alerts_json) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(AlertError::WriteError.into()),
+    }
+}
+
+pub fn add_alert(path: &Path, from: &str, to: &str, condition: AlertCondition, reference_rate: Option<Decimal>) -> Result<Alert> {
+    let mut alerts = read_alerts(path)?;
+    let id = alerts.iter().map(|alert| alert.id).max().unwrap_or(0) + 1;
+    let alert = Alert { id, from: from.to_uppercase(), to: to.to_uppercase(), condition, reference_rate, met: false };
+    alerts.push(alert.clone());
+    write_alerts(path, &alerts)?;
+    Ok(alert)
+}
+
+pub fn remove_alert(path: &Path, id: u32) -> Result<()> {
+    let mut alerts = read_alerts(path)?;
+    let count = alerts.len();
+    alerts.retain(|alert| alert.id != id);
+    if alerts.len() == count {
+        return Err(AlertError::NotFound(id).into());
+    }
+    write_alerts(path, &alerts)
+}
+
+// Checks the alerts for pairs the table contains and updates their state.
+pub fn evaluate_alerts(alerts: &mut [Alert], table: &ApiMultirateResponse) -> Vec<TriggeredAlert> {
+    let mut triggered = Vec::new();
+    for alert in alerts.iter_mut() {
+        if !table_contains(table, &alert.from) || !table_contains(table, &alert.to) {
+            continue;
+        }
+        let rate = match derive_rate(table, &alert.from, &alert.to) {
+            Ok(rate) => rate.rate,
+            Err(_) => continue,
+        };
+        let (trigger, previous_rate) = match alert.condition {
+            AlertCondition::Above(threshold) | AlertCondition::Below(threshold) => {
+                let met = match alert.condition {
+                    AlertCondition::Above(_) => rate > threshold,
+                    _ => rate < threshold,
+                };
+                let trigger = met && !alert.met;
+                alert.met = met;
+                (trigger, None)
+            }
+            AlertCondition::Change(percent) => match alert.reference_rate {
+                Some(reference_rate) if !reference_rate.is_zero() => {
+                    let change = ((rate - reference_rate) * Decimal::ONE_HUNDRED / reference_rate).abs();
+                    if change >= percent {
+                        alert.reference_rate = Some(rate);
+                        (true, Some(reference_rate))
+                    } else {
+                        (false, None)
+                    }
+                }
+                _ => {
+                    alert.reference_rate = Some(rate);
+                    (false, None)
+                }
+            },
+        };
+        if trigger {
+            triggered.push(TriggeredAlert {
+                alert: alert.clone(),
+                rate,
+                previous_rate,
+                time_last_update_utc: table.time_last_update_utc.clone(),
+            });
+        }
+    }
+    triggered
+}
+
+async fn run_command(command: &str, triggered: &TriggeredAlert) -> Result<()> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(command);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command);
+        process
+    };
+    let status = process
+        .env("ALERT_ID", triggered.alert.id.to_string())
+        .env("ALERT_PAIR", format!("{}/{}", triggered.alert.from, triggered.alert.to))
+        .env("ALERT_CONDITION", triggered.alert.condition.to_string())
+        .env("ALERT_RATE", triggered.rate.to_string())
+        .env("ALERT_MESSAGE", triggered.message())
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(AlertError::CommandFailed(status.to_string()).into());
+    }
+    Ok(())
+}
+
+// The alert is sent while the rates are being fetched, a webhook that doesn't answer can't hold that up for long.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn post_webhook(url: &str, triggered: &TriggeredAlert, timeout: Duration) -> Result<()> {
+    let payload = json!({
+        "id": triggered.alert.id,
+        "from": triggered.alert.from,
+        "to": triggered.alert.to,
+        "condition": triggered.alert.condition.to_string(),
+        "rate": triggered.rate.to_string(),
+        "message": triggered.message(),
+        "time_last_update_utc": triggered.time_last_update_utc,
+    });
+    http_client()?.post(url).timeout(timeout).json(&payload).send().await?.error_for_status()?;
+    Ok(())
+}
+
+// Prints the alert and sends it to the configured command and webhook. Failures are printed, they don't stop the command that fetched the rates.
+// Alerts are checked in the middle of other commands, so everything goes to stderr and their output stays parseable.
+pub async fn notify(triggered: &TriggeredAlert) {
+    eprintln!("{}", triggered.message());
+    let config = match read_config() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    if let Some(command) = &config.alert_command {
+        if let Err(e) = run_command(command, triggered).await {
+            eprintln!("Error running alert command: {}", e);
+        }
+    }
+    if let Some(url) = &config.alert_webhook {
+        if let Err(e) = post_webhook(url, triggered, WEBHOOK_TIMEOUT).await {
+            eprintln!("Error calling alert webhook: {}", e);
+        }
+    }
+}
+
+// Called with every newly fetched table.
+pub async fn check_alerts(path: &Path, table: &ApiMultirateResponse) -> Result<()> {
+    let mut alerts = read_alerts(path)?;
+    if alerts.is_empty() {
+        return Ok(());
+    }
+    let triggered = evaluate_alerts(&mut alerts, table);
+    write_alerts(path, &alerts)?;
+    for triggered in &triggered {
+        notify(triggered).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::Value;
+    use super::{add_alert, evaluate_alerts, parse_condition, parse_pair, post_webhook, read_alerts, remove_alert, run_command, AlertCondition};
+    use crate::api::ApiMultirateResponse;
+    use crate::test_server::TestServer;
+    use crate::test_support::{decimal, temp_path, LATEST_USD};
+
+
+    // The recorded USD table with the EUR rate changed.
+    fn table(eur_rate: &str) -> ApiMultirateResponse {
+        let mut table: ApiMultirateResponse = serde_json::from_str(LATEST_USD).unwrap();
+        table.conversion_rates.insert("EUR", decimal(eur_rate)).unwrap();
+        assert_eq!(table.conversion_rates.get("EUR"), Some(decimal(eur_rate)));
+        table
+    }
+
+    fn alerts_file(name: &str) -> std::path::PathBuf {
+        temp_path(&format!("alerts-{}", name))
+    }
+
+    #[test]
+    fn test_parse_alert() {
+        assert_eq!(parse_pair("eurusd").unwrap(), ("EUR".to_string(), "USD".to_string()));
+        assert_eq!(parse_pair("USD/JPY").unwrap(), ("USD".to_string(), "JPY".to_string()));
+        assert!(parse_pair("EURUS").is_err());
+        assert!(parse_pair("EU/RUSD").is_err());
+        assert_eq!(parse_condition("above", "1.12").unwrap(), AlertCondition::Above(decimal("1.12")));
+        assert_eq!(parse_condition("change", "2%").unwrap(), AlertCondition::Change(decimal("2")));
+        assert!(parse_condition("above", "2%").is_err());
+        assert!(parse_condition("below", "-1").is_err());
+        assert!(parse_condition("around", "1.1").is_err());
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let path = alerts_file("add");
+        add_alert(&path, "eur", "usd", AlertCondition::Above(decimal("1.12")), None).unwrap();
+        let second = add_alert(&path, "USD", "JPY", AlertCondition::Change(decimal("2")), Some(decimal("148.9213"))).unwrap();
+        assert_eq!(second.id, 2);
+        remove_alert(&path, 1).unwrap();
+        assert!(remove_alert(&path, 1).is_err());
+        let alerts = read_alerts(&path).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].condition.to_string(), "change 2%");
+        assert_eq!(alerts[0].reference_rate, Some(decimal("148.9213")));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_threshold_alerts_trigger_on_crossing() {
+        let path = alerts_file("threshold");
+        add_alert(&path, "EUR", "USD", AlertCondition::Above(decimal("1.09")), None).unwrap();
+        add_alert(&path, "EUR", "USD", AlertCondition::Below(decimal("1.08")), None).unwrap();
+        let mut alerts = read_alerts(&path).unwrap();
+        // EUR/USD is 1 / 0.9181 = 1.0892...
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9181"));
+        assert!(triggered.is_empty());
+        // 1 / 0.9100 = 1.0989...
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9100"));
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].message(), "Alert 1: EUR/USD is above 1.09 (1.0989010989).");
+        assert!(evaluate_alerts(&mut alerts, &table("0.9050")).is_empty());
+        // 1 / 0.9300 = 1.0752...
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9300"));
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].alert.id, 2);
+        assert_eq!(evaluate_alerts(&mut alerts, &table("0.9100")).len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_change_alert_resets_reference() {
+        let path = alerts_file("change");
+        add_alert(&path, "USD", "EUR", AlertCondition::Change(decimal("2")), None).unwrap();
+        let mut alerts = read_alerts(&path).unwrap();
+        assert!(evaluate_alerts(&mut alerts, &table("0.9000")).is_empty());
+        assert_eq!(alerts[0].reference_rate, Some(decimal("0.9000")));
+        assert!(evaluate_alerts(&mut alerts, &table("0.9170")).is_empty());
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9180"));
+        assert_eq!(triggered[0].message(), "Alert 1: USD/EUR changed by +2% from 0.9 to 0.918.");
+        assert_eq!(alerts[0].reference_rate, Some(decimal("0.9180")));
+        let triggered = evaluate_alerts(&mut alerts, &table("0.8900"));
+        assert_eq!(triggered[0].message(), "Alert 1: USD/EUR changed by -3.05% from 0.918 to 0.89.");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let server = TestServer::start(|_| (200, "{}".to_string())).await;
+        let path = alerts_file("webhook");
+        add_alert(&path, "USD", "EUR", AlertCondition::Below(decimal("0.92")), None).unwrap();
+        let mut alerts = read_alerts(&path).unwrap();
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9181"));
+        post_webhook(&format!("{}/alerts", server.url()), &triggered[0], Duration::from_secs(5)).await.unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert!(requests[0].headers.iter().any(|(name, value)| name == "user-agent" && value.starts_with("currency-converter/")));
+        assert_eq!(requests[0].path, "/alerts");
+        let payload: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(payload["from"], "USD");
+        assert_eq!(payload["condition"], "below 0.92");
+        assert_eq!(payload["rate"], "0.9181");
+        assert_eq!(payload["message"], "Alert 1: USD/EUR is below 0.92 (0.9181).");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_timeout() {
+        // Connections are queued by the system, but nothing ever reads the request or answers it.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let path = alerts_file("webhook-timeout");
+        add_alert(&path, "USD", "EUR", AlertCondition::Below(decimal("0.92")), None).unwrap();
+        let mut alerts = read_alerts(&path).unwrap();
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9181"));
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+        let started = std::time::Instant::now();
+        assert!(post_webhook(&url, &triggered[0], Duration::from_millis(200)).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command() {
+        let path = alerts_file("command");
+        let output = alerts_file("command-output");
+        add_alert(&path, "USD", "EUR", AlertCondition::Above(decimal("0.91")), None).unwrap();
+        let mut alerts = read_alerts(&path).unwrap();
+        let triggered = evaluate_alerts(&mut alerts, &table("0.9181"));
+        let command = format!("echo \"$ALERT_PAIR $ALERT_RATE\" > {}", output.display());
+        run_command(&command, &triggered[0]).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "USD/EUR 0.9181\n");
+        assert!(run_command("exit 3", &triggered[0]).await.is_err());
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(output);
+    }
+}
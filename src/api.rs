@@ -1,29 +1,110 @@
-use reqwest::get;
+use std::future::Future;
+use std::time::Duration as RequestTimeout;
+use reqwest::{Client, StatusCode};
 use serde::{Serialize, Deserialize};
-use serde_json::Value;
+use serde::de::DeserializeOwned;
 use anyhow::Result;
+use rust_decimal::Decimal;
+use async_trait::async_trait;
+use chrono::{Datelike, Duration, NaiveDate};
 use thiserror::Error;
 use crate::config::read_config;
+use crate::provider::RateProvider;
+use crate::rate_table::RateTable;
+use crate::retry::RetryPolicy;
 
 // The API for the exchange rate service is provided by https://v6.exchangerate-api.com.
-// I know reading the API key every time is not optimal, but i think it's good enough for this project.
+// All requests go through one ApiClient, which keeps the connections open between requests of the same command,
+// decodes every response the same way and retries the ones that failed for a passing reason (see retry.rs). The API key is read once, with the rest of the config.
+// The base URL can be changed in the config file or with the API_BASE_URL environment variable.
 
-const BASE_API_URL: &str = "https://v6.exchangerate-api.com/v6";
+pub const BASE_API_URL: &str = "https://v6.exchangerate-api.com/v6";
+const USER_AGENT: &str = concat!("currency-converter/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: RequestTimeout = RequestTimeout::from_secs(10);
+// The full history of a paid plan can take a while, everything else is small.
+const REQUEST_TIMEOUT: RequestTimeout = RequestTimeout::from_secs(30);
 
+// What the API answered to a failed request, kept for diagnostics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+#[derive(Error, Debug)]
+enum ApiError {
+    #[error("No API key set.")]
+    MissingApiKey,
+}
+
+// Errors of the providers. The ones coming from an API response keep its status and body,
+// the ones found without a request (e.g. a currency missing from a cached table) have none.
 #[derive(Error, Debug)]
-enum RequestError {
+pub enum RequestError {
     #[error("Unsupported currency.")]
-    UnsupportedCurrency,
+    UnsupportedCurrency(Option<HttpResponse>),
     #[error("Malformed request.")]
-    MalformedRequest,
+    MalformedRequest(Option<HttpResponse>),
     #[error("Invalid API key.")]
-    InvalidApiKey,
+    InvalidApiKey(Option<HttpResponse>),
     #[error("Inactive account.")]
-    InactiveAccount,
+    InactiveAccount(Option<HttpResponse>),
     #[error("Quota reached.")]
-    QuotaReached,
+    QuotaReached(Option<HttpResponse>),
+    #[error("No data available for the date.")]
+    NoDataAvailable(Option<HttpResponse>),
+    #[error("Plan upgrade required.")]
+    PlanUpgradeRequired(Option<HttpResponse>),
+    #[error("Invalid response from the API.")]
+    InvalidResponse(Option<HttpResponse>),
     #[error("Unknown error.")]
-    UnknownError
+    UnknownError(Option<HttpResponse>),
+}
+
+impl RequestError {
+    // Maps the "error-type" of an error response, keeping the response.
+    fn from_error_type(error_type: &str, response: HttpResponse) -> RequestError {
+        let response = Some(response);
+        match error_type {
+            "unsupported-code" => RequestError::UnsupportedCurrency(response),
+            "malformed-request" => RequestError::MalformedRequest(response),
+            "invalid-key" => RequestError::InvalidApiKey(response),
+            "inactive-account" => RequestError::InactiveAccount(response),
+            "quota-reached" => RequestError::QuotaReached(response),
+            "no-data-available" => RequestError::NoDataAvailable(response),
+            "plan-upgrade-required" => RequestError::PlanUpgradeRequired(response),
+            _ => RequestError::UnknownError(response),
+        }
+    }
+
+    // Short name of the error, matching the "error-type" values of the API.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RequestError::UnsupportedCurrency(_) => "unsupported-code",
+            RequestError::MalformedRequest(_) => "malformed-request",
+            RequestError::InvalidApiKey(_) => "invalid-key",
+            RequestError::InactiveAccount(_) => "inactive-account",
+            RequestError::QuotaReached(_) => "quota-reached",
+            RequestError::NoDataAvailable(_) => "no-data-available",
+            RequestError::PlanUpgradeRequired(_) => "plan-upgrade-required",
+            RequestError::InvalidResponse(_) => "invalid-response",
+            RequestError::UnknownError(_) => "unknown-error",
+        }
+    }
+
+    pub fn response(&self) -> Option<&HttpResponse> {
+        match self {
+            RequestError::UnsupportedCurrency(response)
+            | RequestError::MalformedRequest(response)
+            | RequestError::InvalidApiKey(response)
+            | RequestError::InactiveAccount(response)
+            | RequestError::QuotaReached(response)
+            | RequestError::NoDataAvailable(response)
+            | RequestError::PlanUpgradeRequired(response)
+            | RequestError::InvalidResponse(response)
+            | RequestError::UnknownError(response) => response.as_ref(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -36,7 +117,7 @@ pub struct ApiMultirateResponse {
     pub time_next_update_unix: i64,
     pub time_next_update_utc: String,
     pub base_code: String,
-    pub conversion_rates: Value
+    pub conversion_rates: RateTable,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,11 +131,12 @@ pub struct ApiRateResponse {
     pub time_next_update_utc: String,
     pub base_code: String,
     pub target_code: String,
-    pub conversion_rate: f64,
+    pub conversion_rate: Decimal,
 }
 
+// Pair rate with data about the target currency, only available on the Pro and Business plans.
 #[derive(Serialize, Deserialize)]
-pub struct ApiConversionResponse {
+pub struct ApiEnrichedResponse {
     pub result: String,
     pub documentation: String,
     pub terms_of_use: String,
@@ -64,8 +146,64 @@ pub struct ApiConversionResponse {
     pub time_next_update_utc: String,
     pub base_code: String,
     pub target_code: String,
-    pub conversion_rate: f64,
-    pub conversion_result: f64,
+    pub conversion_rate: Decimal,
+    pub target_data: ApiTargetData,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiTargetData {
+    pub locale: String,
+    pub two_letter_code: String,
+    pub currency_name: String,
+    pub currency_name_short: String,
+    // Unicode code points in hex, separated by commas, e.g. "0052,0024" for R$.
+    pub display_symbol: String,
+    pub flag_url: String,
+}
+
+impl ApiTargetData {
+    // The display symbol as text, None when a code point is not valid.
+    pub fn symbol(&self) -> Option<String> {
+        self.display_symbol
+            .split(',')
+            .map(|code_point| u32::from_str_radix(code_point.trim(), 16).ok().and_then(char::from_u32))
+            .collect()
+    }
+}
+
+// Historical rates have no update times, only the date they are for.
+#[derive(Serialize, Deserialize)]
+pub struct ApiHistoryResponse {
+    pub result: String,
+    pub documentation: String,
+    pub terms_of_use: String,
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub base_code: String,
+    pub conversion_rates: RateTable,
+}
+
+impl ApiHistoryResponse {
+    // Converts the response to a table of all rates, updated at midnight UTC of the date.
+    pub fn into_table(self) -> Result<ApiMultirateResponse> {
+        let last_update = match NaiveDate::from_ymd_opt(self.year, self.month, self.day).and_then(|date| date.and_hms_opt(0, 0, 0)) {
+            Some(time) => time.and_utc(),
+            None => return Err(RequestError::UnknownError(None).into()),
+        };
+        let next_update = last_update + Duration::days(1);
+        Ok(ApiMultirateResponse {
+            result: self.result,
+            documentation: self.documentation,
+            terms_of_use: self.terms_of_use,
+            time_last_update_unix: last_update.timestamp(),
+            time_last_update_utc: last_update.to_rfc2822(),
+            time_next_update_unix: next_update.timestamp(),
+            time_next_update_utc: next_update.to_rfc2822(),
+            base_code: self.base_code,
+            conversion_rates: self.conversion_rates,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -75,111 +213,283 @@ pub struct ErrorResponse {
     pub error_type: String,
 }
 
-pub async fn get_all_exchange_rates(base_currency: &str) -> Result<ApiMultirateResponse> {
-    let api_key = read_config()?.api_key;
-    let url = format!("{}/{}/latest/{}", BASE_API_URL, api_key, base_currency);
-    let response = get(&url).await?;
-    let status = response.status();
-    if !status.is_success() {
-        let error = match response.json::<ErrorResponse>().await {
-            Ok(error) => error,
-            Err(_) => return Err(RequestError::UnknownError.into())
-        };
-        match error.error_type.as_str() {
-            "unsupported-code" => return Err(RequestError::UnsupportedCurrency.into()),
-            "malformed-request" => return Err(RequestError::MalformedRequest.into()),
-            "invalid-key" => return Err(RequestError::InvalidApiKey.into()),
-            "inactive-account" => return Err(RequestError::InactiveAccount.into()),
-            "quota-reached" => return Err(RequestError::QuotaReached.into()),
-            _ => return Err(RequestError::UnknownError.into())
+#[derive(Serialize, Deserialize)]
+pub struct ApiCodesResponse {
+    pub result: String,
+    pub documentation: String,
+    pub terms_of_use: String,
+    pub supported_codes: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiQuotaResponse {
+    pub result: String,
+    pub documentation: String,
+    pub terms_of_use: String,
+    pub plan_quota: u64,
+    pub requests_remaining: u64,
+    pub refresh_day_of_month: u32,
+}
+
+// Decodes a response of the API: the expected body on success, otherwise the error it describes.
+// The API answers errors with an "error-type", anything else it returns is an unknown or invalid response.
+fn decode_response<T: DeserializeOwned>(status: StatusCode, body: String) -> std::result::Result<T, RequestError> {
+    let response = HttpResponse { status: status.as_u16(), body };
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(&response.body) {
+        if error.result == "error" || !status.is_success() {
+            return Err(RequestError::from_error_type(&error.error_type, response));
         }
     }
-    let exchange_rate_response = response.json::<ApiMultirateResponse>().await?;
-    Ok(exchange_rate_response)
+    if !status.is_success() {
+        return Err(RequestError::UnknownError(Some(response)));
+    }
+    match serde_json::from_str::<T>(&response.body) {
+        Ok(decoded) => Ok(decoded),
+        Err(_) => Err(RequestError::InvalidResponse(Some(response))),
+    }
 }
 
-pub async fn get_exchange_rate(from: &str, to: &str) -> Result<ApiRateResponse> {
-    let api_key = read_config()?.api_key;
-    let url = format!("{}/{}/pair/{}/{}", BASE_API_URL, api_key, from, to);
-    let response = get(&url).await?;
-    let status = response.status();
-    if !status.is_success() {
-        let error = match response.json::<ErrorResponse>().await {
-            Ok(error) => error,
-            Err(_) => return Err(RequestError::UnknownError.into())
-        };
-        match error.error_type.as_str() {
-            "unsupported-code" => return Err(RequestError::UnsupportedCurrency.into()),
-            "malformed-request" => return Err(RequestError::MalformedRequest.into()),
-            "invalid-key" => return Err(RequestError::InvalidApiKey.into()),
-            "inactive-account" => return Err(RequestError::InactiveAccount.into()),
-            "quota-reached" => return Err(RequestError::QuotaReached.into()),
-            _ => return Err(RequestError::UnknownError.into())
+// Every HTTP request of the program identifies itself the same way and gives up after the same timeouts.
+pub fn http_client() -> Result<Client> {
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    Ok(client)
+}
+
+// HTTP client shared by the providers: the same user agent and timeouts for every request,
+// and the retries of the policy for the ones that failed for a passing reason.
+pub struct ApiClient {
+    client: Client,
+    retry_policy: RetryPolicy,
+}
+
+impl ApiClient {
+    pub fn new(retry_policy: RetryPolicy) -> Result<ApiClient> {
+        Ok(ApiClient { client: http_client()?, retry_policy })
+    }
+
+    async fn with_retries<T, F, R>(&self, request: F) -> Result<T>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.retry_policy.max_attempts && self.retry_policy.should_retry(&e) => {
+                    tokio::time::sleep(self.retry_policy.next_delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
         }
     }
-    let exchange_rate_response = response.json::<ApiRateResponse>().await?;
-    Ok(exchange_rate_response)
+
+    async fn get_json_once<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        Ok(decode_response(status, body)?)
+    }
+
+    // A JSON response of ExchangeRate-API, see decode_response.
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.with_retries(|| self.get_json_once(url)).await
+    }
+
+    async fn get_text_once(&self, url: &str) -> Result<String> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(RequestError::UnknownError(Some(HttpResponse { status: status.as_u16(), body })).into());
+        }
+        Ok(body)
+    }
+
+    // The body of a successful response as it is, e.g. the XML files of the ECB.
+    pub async fn get_text(&self, url: &str) -> Result<String> {
+        self.with_retries(|| self.get_text_once(url)).await
+    }
 }
 
-pub async fn convert(from: &str, to: &str, amount: f64) -> Result<ApiConversionResponse> {
-    let api_key = read_config()?.api_key;
-    let url = format!("{}/{}/pair/{}/{}/{}", BASE_API_URL, api_key, from, to, amount);
-    let response = get(&url).await?;
-    let status = response.status();
-    if !status.is_success() {
-        let error = match response.json::<ErrorResponse>().await {
-            Ok(error) => error,
-            Err(_) => return Err(RequestError::UnknownError.into())
+pub struct ExchangeRateApi {
+    client: ApiClient,
+    base_url: String,
+    api_key: String,
+}
+
+impl ExchangeRateApi {
+    pub fn new(base_url: &str, api_key: String, retry_policy: RetryPolicy) -> Result<ExchangeRateApi> {
+        Ok(ExchangeRateApi {
+            client: ApiClient::new(retry_policy)?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+
+    pub fn from_config() -> Result<ExchangeRateApi> {
+        // The key comes from the API_KEY variable or the config, it's read once for all the requests.
+        let config = read_config()?;
+        let api_key = match config.api_key.clone() {
+            Some(api_key) => api_key,
+            None => return Err(ApiError::MissingApiKey.into()),
         };
-        match error.error_type.as_str() {
-            "unsupported-code" => return Err(RequestError::UnsupportedCurrency.into()),
-            "malformed-request" => return Err(RequestError::MalformedRequest.into()),
-            "invalid-key" => return Err(RequestError::InvalidApiKey.into()),
-            "inactive-account" => return Err(RequestError::InactiveAccount.into()),
-            "quota-reached" => return Err(RequestError::QuotaReached.into()),
-            _ => return Err(RequestError::UnknownError.into())
-        }
+        let retry_policy = RetryPolicy::from_config(&config);
+        let base_url = config.base_url.unwrap_or_else(|| BASE_API_URL.to_string());
+        ExchangeRateApi::new(&base_url, api_key, retry_policy)
+    }
+
+    // GET {base_url}/{api_key}/{path}, e.g. path "pair/USD/EUR".
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.client.get_json(&format!("{}/{}/{}", self.base_url, self.api_key, path)).await
+    }
+
+    // Requests left in the current month, only this provider has a quota.
+    pub async fn quota(&self) -> Result<ApiQuotaResponse> {
+        self.get("quota").await
+    }
+}
+
+#[async_trait]
+impl RateProvider for ExchangeRateApi {
+    fn name(&self) -> &'static str {
+        "exchangerate-api"
+    }
+
+    async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse> {
+        self.get(&format!("latest/{}", base_currency)).await
+    }
+
+    async fn pair(&self, from: &str, to: &str) -> Result<ApiRateResponse> {
+        self.get(&format!("pair/{}/{}", from, to)).await
+    }
+
+    async fn history(&self, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse> {
+        let path = format!("history/{}/{}/{}/{}", base_currency, date.year(), date.month(), date.day());
+        let history_response = self.get::<ApiHistoryResponse>(&path).await?;
+        history_response.into_table()
+    }
+
+    async fn supported_codes(&self) -> Result<Vec<(String, String)>> {
+        let codes_response = self.get::<ApiCodesResponse>("codes").await?;
+        Ok(codes_response.supported_codes)
+    }
+
+    // Only the Pro and Business plans include the enriched data.
+    async fn enriched(&self, from: &str, to: &str) -> Result<ApiEnrichedResponse> {
+        self.get(&format!("enriched/{}/{}", from, to)).await
     }
-    let exchange_rate_response = response.json::<ApiConversionResponse>().await?;
-    Ok(exchange_rate_response)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_all_exchange_rates, get_exchange_rate, convert};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use reqwest::StatusCode;
+    use super::{decode_response, ApiTargetData, ExchangeRateApi, HttpResponse, RequestError};
+    use crate::retry::RetryPolicy;
+    use crate::provider::RateProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::test_server::{RecordedRequest, TestServer, DROP_CONNECTION};
+    use crate::test_support::{HISTORY_USD, LATEST_USD};
+
+    // Responses recorded from https://v6.exchangerate-api.com, served by a local test server.
+    const PAIR_USD_EUR: &str = include_str!("../tests/fixtures/exchangerate-api/pair-USD-EUR.json");
+    const CODES: &str = include_str!("../tests/fixtures/exchangerate-api/codes.json");
+    const QUOTA: &str = include_str!("../tests/fixtures/exchangerate-api/quota.json");
+    const ENRICHED_USD_EUR: &str = include_str!("../tests/fixtures/exchangerate-api/enriched-USD-EUR.json");
+    const UNSUPPORTED_CODE: &str = include_str!("../tests/fixtures/exchangerate-api/error-unsupported-code.json");
+    const MALFORMED_REQUEST: &str = include_str!("../tests/fixtures/exchangerate-api/error-malformed-request.json");
+    const INVALID_KEY: &str = include_str!("../tests/fixtures/exchangerate-api/error-invalid-key.json");
+    const INACTIVE_ACCOUNT: &str = include_str!("../tests/fixtures/exchangerate-api/error-inactive-account.json");
+    const QUOTA_REACHED: &str = include_str!("../tests/fixtures/exchangerate-api/error-quota-reached.json");
+    const NO_DATA_AVAILABLE: &str = include_str!("../tests/fixtures/exchangerate-api/error-no-data-available.json");
+    const PLAN_UPGRADE_REQUIRED: &str = include_str!("../tests/fixtures/exchangerate-api/error-plan-upgrade-required.json");
+
+    const TEST_KEY: &str = "test-key";
+
+    // Imitates the API: the key decides about account errors, the path about everything else.
+    fn recorded_response(request: &RecordedRequest) -> (u16, String) {
+        let segments = request.path.trim_start_matches('/').split('/').collect::<Vec<&str>>();
+        match segments[0] {
+            TEST_KEY => (),
+            "inactive-key" => return (403, INACTIVE_ACCOUNT.to_string()),
+            "exhausted-key" => return (429, QUOTA_REACHED.to_string()),
+            "broken-key" => return (500, "Internal Server Error".to_string()),
+            "garbled-key" => return (200, "<html>Maintenance</html>".to_string()),
+            // Free plans don't include historical rates or enriched data.
+            "free-key" if matches!(segments.get(1), Some(&"history") | Some(&"enriched")) => return (403, PLAN_UPGRADE_REQUIRED.to_string()),
+            "free-key" => (),
+            _ => return (403, INVALID_KEY.to_string()),
+        }
+        match segments[1..] {
+            ["latest", "USD"] => (200, LATEST_USD.to_string()),
+            ["pair", "USD", "EUR"] => (200, PAIR_USD_EUR.to_string()),
+            ["codes"] => (200, CODES.to_string()),
+            ["quota"] => (200, QUOTA.to_string()),
+            ["enriched", "USD", "EUR"] => (200, ENRICHED_USD_EUR.to_string()),
+            ["history", "USD", "2024", "3", "14"] => (200, HISTORY_USD.to_string()),
+            ["history", "USD", year, _, _] if year < "1990" => (404, NO_DATA_AVAILABLE.to_string()),
+            ["latest", code] | ["pair", code, _] if code.is_empty() => (400, MALFORMED_REQUEST.to_string()),
+            ["latest", _] | ["pair", _, _] => (404, UNSUPPORTED_CODE.to_string()),
+            _ => (400, MALFORMED_REQUEST.to_string()),
+        }
+    }
+
+    async fn recorded_api(api_key: &str) -> (TestServer, ExchangeRateApi) {
+        let server = TestServer::start(recorded_response).await;
+        let api = ExchangeRateApi::new(&server.url(), api_key.to_string(), RetryPolicy::without_delay(1)).unwrap();
+        (server, api)
+    }
+
+    async fn assert_latest_error(api_key: &str, base_currency: &str, expected: &str) {
+        let (_server, api) = recorded_api(api_key).await;
+        match api.latest(base_currency).await {
+            Ok(_) => {
+                panic!("Expected an error, but got a response.");
+            }
+            Err(e) => {
+                assert_eq!(e.to_string(), expected);
+            }
+        }
+    }
 
     #[tokio::test]
     async fn test_get_all_exchange_rates_correct() {
-        let response = get_all_exchange_rates("USD").await;
+        let (server, api) = recorded_api(TEST_KEY).await;
+        let response = api.latest("USD").await;
         match response {
             Ok(response) => {
                 assert_eq!(response.base_code, "USD");
+                assert_eq!(response.conversion_rates.get("EUR"), Some(Decimal::new(9181, 4)));
             }
             Err(e) => {
                 panic!("Error getting exchange rates: {}", e);
             }
         }
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/test-key/latest/USD");
     }
 
     #[tokio::test]
     async fn test_get_all_exchange_rates_incorrect() {
-        match get_all_exchange_rates("UST").await {
-            Ok(_) => {
-                panic!("Expected an error, but got a response.");
-            }
-            Err(e) => {
-                assert_eq!(e.to_string(), "Unsupported currency.");
-            }
-        }
+        assert_latest_error(TEST_KEY, "UST", "Unsupported currency.").await;
     }
 
     #[tokio::test]
     async fn test_get_rate_both_correct() {
-        match get_exchange_rate("USD", "EUR").await {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.pair("USD", "EUR").await {
             Ok(response) => {
                 assert_eq!(response.base_code, "USD");
                 assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_rate.is_nan(), false);
+                assert_eq!(response.conversion_rate, Decimal::new(9181, 4));
             }
             Err(e) => {
                 panic!("Error getting exchange rate: {}", e);
@@ -189,7 +499,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rate_left_wrong() {
-        match get_exchange_rate("UST", "EUR").await {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.pair("UST", "EUR").await {
             Ok(_) => {
                 panic!("Expected an error, but got a response.");
             }
@@ -201,7 +512,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rate_right_wrong() {
-        match get_exchange_rate("USD", "EUX").await {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.pair("USD", "EUX").await {
             Ok(_) => {
                 panic!("Expected an error, but got a response.");
             }
@@ -213,7 +525,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rate_both_wrong() {
-        match get_exchange_rate("UST", "EUX").await {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.pair("UST", "EUX").await {
             Ok(_) => {
                 panic!("Expected an error, but got a response.");
             }
@@ -224,92 +537,216 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_convert_all_correct() {
-        match convert("USD", "EUR", 100.into()).await {
-            Ok(response) => {
-                assert_eq!(response.base_code, "USD");
-                assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_result.is_nan(), false);
+    async fn test_supported_codes() {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.supported_codes().await {
+            Ok(codes) => {
+                assert_eq!(codes.len(), 6);
+                assert_eq!(codes[2], ("EUR".to_string(), "Euro".to_string()));
             }
             Err(e) => {
-                panic!("Error converting currency: {}", e);
+                panic!("Error getting supported currencies: {}", e);
             }
         }
+    }
 
-        match convert("USD", "EUR", 100.0.into()).await {
-            Ok(response) => {
-                assert_eq!(response.base_code, "USD");
-                assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_result.is_nan(), false);
-            }
-            Err(e) => {
-                panic!("Error converting currency: {}", e);
-            }
+    #[tokio::test]
+    async fn test_quota() {
+        let (server, api) = recorded_api(TEST_KEY).await;
+        let quota = api.quota().await.unwrap();
+        assert_eq!(quota.plan_quota, 1500);
+        assert_eq!(quota.requests_remaining, 1185);
+        assert_eq!(quota.refresh_day_of_month, 17);
+        assert_eq!(server.requests()[0].path, "/test-key/quota");
+        let (_server, api) = recorded_api("exhausted-key").await;
+        match api.quota().await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "Quota reached."),
         }
+    }
 
-        match convert("USD", "EUR", 4231.1296.into()).await {
-            Ok(response) => {
-                assert_eq!(response.base_code, "USD");
-                assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_result.is_nan(), false);
-            }
-            Err(e) => {
-                panic!("Error converting currency: {}", e);
-            }
+    #[tokio::test]
+    async fn test_enriched() {
+        let (server, api) = recorded_api(TEST_KEY).await;
+        let enriched = api.enriched("USD", "EUR").await.unwrap();
+        assert_eq!(enriched.conversion_rate, Decimal::new(9181, 4));
+        assert_eq!(enriched.target_data.locale, "European Union");
+        assert_eq!(enriched.target_data.two_letter_code, "EU");
+        assert_eq!(enriched.target_data.symbol(), Some("€".to_string()));
+        assert_eq!(server.requests()[0].path, "/test-key/enriched/USD/EUR");
+        let (_server, api) = recorded_api("free-key").await;
+        match api.enriched("USD", "EUR").await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "Plan upgrade required."),
         }
     }
 
-    
+    #[test]
+    fn test_display_symbol() {
+        let mut target_data = ApiTargetData {
+            locale: "Brazil".to_string(),
+            two_letter_code: "BR".to_string(),
+            currency_name: "Brazilian Real".to_string(),
+            currency_name_short: "Real".to_string(),
+            display_symbol: "0052,0024".to_string(),
+            flag_url: "https://www.exchangerate-api.com/img/docs/currency-flags/brl.gif".to_string(),
+        };
+        assert_eq!(target_data.symbol(), Some("R$".to_string()));
+        target_data.display_symbol = "D800".to_string();
+        assert_eq!(target_data.symbol(), None);
+    }
+
     #[tokio::test]
-    async fn test_convert_wrong_left_currency() {
-        match convert("UST", "EUR", 100.into()).await {
-            Ok(_) => {
-                panic!("Expected an error, but got a response.");
+    async fn test_history() {
+        let (server, api) = recorded_api(TEST_KEY).await;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        match api.history("USD", date).await {
+            Ok(table) => {
+                assert_eq!(table.base_code, "USD");
+                assert_eq!(table.time_last_update_utc, "Thu, 14 Mar 2024 00:00:00 +0000");
+                assert_eq!(table.conversion_rates.get("EUR"), Some(Decimal::new(9153, 4)));
             }
             Err(e) => {
-                assert_eq!(e.to_string(), "Unsupported currency.");
+                panic!("Error getting historical exchange rates: {}", e);
             }
         }
+        assert_eq!(server.requests()[0].path, "/test-key/history/USD/2024/3/14");
     }
 
     #[tokio::test]
-    async fn test_convert_wrong_right_currency() {
-        match convert("USD", "EUX", 100.into()).await {
-            Ok(_) => {
-                panic!("Expected an error, but got a response.");
-            }
-            Err(e) => {
-                assert_eq!(e.to_string(), "Unsupported currency.");
-            }
+    async fn test_history_errors() {
+        let (_server, api) = recorded_api(TEST_KEY).await;
+        match api.history("USD", NaiveDate::from_ymd_opt(1980, 1, 2).unwrap()).await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "No data available for the date."),
+        }
+        let (_server, api) = recorded_api("free-key").await;
+        match api.history("USD", NaiveDate::from_ymd_opt(2024, 3, 14).unwrap()).await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "Plan upgrade required."),
         }
     }
 
     #[tokio::test]
-    async fn test_convert_small_amount() {
-        match convert("USD", "EUR", 0.00025.into()).await {
-            Ok(response) => {
-                assert_eq!(response.base_code, "USD");
-                assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_result.is_nan(), false);
-            }
-            Err(e) => {
-                panic!("Error converting currency: {}", e);
-            }
+    async fn test_malformed_request() {
+        assert_latest_error(TEST_KEY, "", "Malformed request.").await;
+    }
+
+    #[tokio::test]
+    async fn test_invalid_api_key() {
+        assert_latest_error("wrong-key", "USD", "Invalid API key.").await;
+    }
+
+    #[tokio::test]
+    async fn test_inactive_account() {
+        assert_latest_error("inactive-key", "USD", "Inactive account.").await;
+    }
+
+    #[tokio::test]
+    async fn test_quota_reached() {
+        assert_latest_error("exhausted-key", "USD", "Quota reached.").await;
+    }
+
+    #[tokio::test]
+    async fn test_unknown_error() {
+        assert_latest_error("broken-key", "USD", "Unknown error.").await;
+    }
+
+    async fn latest_request_error(api_key: &str) -> RequestError {
+        let (_server, api) = recorded_api(api_key).await;
+        match api.latest("USD").await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => e.downcast::<RequestError>().unwrap(),
         }
     }
 
     #[tokio::test]
-    async fn test_convert_big_amount() {
-        match convert("USD", "EUR", 326235234543.32452362323.into()).await {
-            Ok(response) => {
-                assert_eq!(response.base_code, "USD");
-                assert_eq!(response.target_code, "EUR");
-                assert_eq!(response.conversion_result.is_nan(), false);
+    async fn test_error_keeps_response() {
+        let error = latest_request_error("broken-key").await;
+        assert_eq!(error.response(), Some(&HttpResponse { status: 500, body: "Internal Server Error".to_string() }));
+        let error = latest_request_error("exhausted-key").await;
+        assert_eq!(error.kind(), "quota-reached");
+        assert_eq!(error.response().unwrap().status, 429);
+        assert!(error.response().unwrap().body.contains("quota-reached"));
+        let error = latest_request_error("garbled-key").await;
+        assert_eq!(error.to_string(), "Invalid response from the API.");
+        assert_eq!(error.response().unwrap().body, "<html>Maintenance</html>");
+    }
+
+    // Answers with the given statuses in turn, then with the recorded table.
+    async fn faulty_api(faults: Vec<u16>, max_attempts: u32) -> (TestServer, ExchangeRateApi) {
+        let calls = AtomicUsize::new(0);
+        let server = TestServer::start(move |request| {
+            match faults.get(calls.fetch_add(1, Ordering::SeqCst)) {
+                Some(&status) => (status, "<html>Service Unavailable</html>".to_string()),
+                None => recorded_response(request),
             }
+        }).await;
+        let api = ExchangeRateApi::new(&server.url(), TEST_KEY.to_string(), RetryPolicy::without_delay(max_attempts)).unwrap();
+        (server, api)
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let (server, api) = faulty_api(vec![503, DROP_CONNECTION, 502], 4).await;
+        let table = api.latest("USD").await.unwrap();
+        assert_eq!(table.base_code, "USD");
+        assert_eq!(server.requests().len(), 4);
+        assert!(server.requests().iter().all(|request| request.path == "/test-key/latest/USD"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let (server, api) = faulty_api(vec![503, 503, 503], 3).await;
+        match api.latest("USD").await {
+            Ok(_) => panic!("Expected an error, but got a response."),
             Err(e) => {
-                panic!("Error converting currency: {}", e);
+                let error = e.downcast::<RequestError>().unwrap();
+                assert_eq!(error.to_string(), "Unknown error.");
+                assert_eq!(error.response().unwrap().status, 503);
             }
         }
+        assert_eq!(server.requests().len(), 3);
+        // Statuses that aren't in the policy are not retried.
+        let (server, api) = faulty_api(vec![418], 3).await;
+        assert!(api.latest("USD").await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_decoded_errors() {
+        let policy = RetryPolicy::default();
+        let decode = |status: u16, body: &str| decode_response::<serde_json::Value>(StatusCode::from_u16(status).unwrap(), body.to_string()).unwrap_err();
+        // A page that isn't the API's JSON is an unknown error with its status, retried for the statuses of the policy.
+        let error = decode(503, "<html>Service Unavailable</html>");
+        assert_eq!(error.kind(), "unknown-error");
+        assert!(policy.should_retry(&error.into()));
+        assert!(!policy.should_retry(&decode(404, "<html>Not Found</html>").into()));
+        // A successful status with a body that can't be decoded is permanent.
+        let error = decode(200, "<html>Maintenance</html>");
+        assert_eq!(error.kind(), "invalid-response");
+        assert!(!policy.should_retry(&error.into()));
+        assert!(!policy.should_retry(&decode(503, UNSUPPORTED_CODE).into()));
     }
-}
 
+    #[tokio::test]
+    async fn test_no_retry_for_permanent_errors() {
+        let server = TestServer::start(recorded_response).await;
+        for (api_key, base_currency) in [("wrong-key", "USD"), (TEST_KEY, "UST"), ("exhausted-key", "USD"), ("inactive-key", "USD")] {
+            let api = ExchangeRateApi::new(&server.url(), api_key.to_string(), RetryPolicy::without_delay(3)).unwrap();
+            assert!(api.latest(base_currency).await.is_err());
+        }
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let (server, api) = recorded_api(TEST_KEY).await;
+        api.pair("USD", "EUR").await.unwrap();
+        api.latest("USD").await.unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let user_agent = requests[0].headers.iter().find(|(name, _)| name == "user-agent").map(|(_, value)| value.as_str());
+        assert_eq!(user_agent, Some(super::USER_AGENT));
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use anyhow::Result;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use thiserror::Error;
+use crate::cache::CachedProvider;
+use crate::currency::validate_currency;
+use crate::engine::{DerivedRate, RoundingMode};
+
+// Converts every row of a CSV file of amounts to a single target currency.
+// The input needs an amount and a currency column, and can have a date column for historical rates.
+// All the other columns are copied to the output, followed by the converted amount, the rate used and its time.
+// A row that can't be converted gets an error instead, so the output always has the same rows as the input.
+
+#[derive(Error, Debug)]
+enum BatchError {
+    #[error("The input file has no {0} column.")]
+    MissingColumn(&'static str),
+    #[error("Error reading the input file: {0}")]
+    ReadError(String),
+    #[error("Error writing the output file.")]
+    WriteError,
+    #[error("Invalid amount: {0}.")]
+    InvalidAmount(String),
+    #[error("Invalid date: {0}. Please use the YYYY-MM-DD format.")]
+    InvalidDate(String),
+}
+
+const OUTPUT_COLUMNS: [&str; 5] = ["target_currency", "converted_amount", "rate", "rate_time_utc", "error"];
+
+pub struct BatchSummary {
+    pub converted: usize,
+    pub failed: usize,
+}
+
+// Rates are looked up once per currency and date, so a file with thousands of rows only needs a few tables.
+struct RateLookup<'a> {
+    provider: &'a CachedProvider,
+    target: String,
+    // Decimal places of the target currency, the converted amounts are rounded to them.
+    decimals: u32,
+    default_date: Option<NaiveDate>,
+    rates: HashMap<(String, Option<NaiveDate>), DerivedRate>,
+}
+
+impl RateLookup<'_> {
+    async fn rate(&mut self, currency: &str, date: Option<NaiveDate>) -> Result<&DerivedRate> {
+        let date = date.or(self.default_date);
+        let key = (currency.to_string(), date);
+        if !self.rates.contains_key(&key) {
+            let rate = match date {
+                Some(date) => self.provider.derived_history_rate(currency, &self.target, date).await?,
+                None => self.provider.derived_rate(currency, &self.target).await?,
+            };
+            self.rates.insert(key.clone(), rate);
+        }
+        Ok(&self.rates[&key])
+    }
+}
+
+fn column(headers: &csv::StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name))
+}
+
+// Converted amount and the rate used for a single row.
+async fn convert_row(lookup: &mut RateLookup<'_>, amount: &str, currency: &str, date: Option<&str>, rounding_mode: RoundingMode) -> Result<(Decimal, DerivedRate)> {
+    let amount = match Decimal::from_str(amount.trim()) {
+        Ok(amount) => amount,
+        Err(_) => return Err(BatchError::InvalidAmount(amount.to_string()).into()),
+    };
+    let currency = validate_currency(currency.trim())?.code;
+    let date = match date.map(str::trim).filter(|date| !date.is_empty()) {
+        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => return Err(BatchError::InvalidDate(date.to_string()).into()),
+        },
+        None => None,
+    };
+    let decimals = lookup.decimals;
+    let rate = lookup.rate(currency, date).await?;
+    let converted = rounding_mode.round(rate.convert(amount)?, decimals);
+    Ok((converted, rate.clone()))
+}
+
+// Rows without a date use the default date, or the latest rates when it's not set.
+pub async fn convert_batch<R: Read, W: Write>(
+    provider: &CachedProvider,
+    reader: R,
+    writer: W,
+    delimiter: u8,
+    target: &str,
+    default_date: Option<NaiveDate>,
+    rounding_mode: RoundingMode,
+) -> Result<BatchSummary> {
+    // Checked before anything is written, an invalid target would only repeat the same error on every row.
+    let target_currency = validate_currency(target)?;
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).flexible(true).from_writer(writer);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Err(BatchError::ReadError(e.to_string()).into()),
+    };
+    let amount_column = column(&headers, "amount").ok_or(BatchError::MissingColumn("amount"))?;
+    let currency_column = column(&headers, "currency").ok_or(BatchError::MissingColumn("currency"))?;
+    let date_column = column(&headers, "date");
+    let output_headers = headers.iter().chain(OUTPUT_COLUMNS).collect::<csv::StringRecord>();
+    if writer.write_record(&output_headers).is_err() {
+        return Err(BatchError::WriteError.into());
+    }
+    let target = target_currency.code.to_string();
+    let mut lookup = RateLookup { provider, target: target.clone(), decimals: target_currency.rounding_decimals(), default_date, rates: HashMap::new() };
+    let mut summary = BatchSummary { converted: 0, failed: 0 };
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => return Err(BatchError::ReadError(e.to_string()).into()),
+        };
+        let amount = record.get(amount_column).unwrap_or_default();
+        let currency = record.get(currency_column).unwrap_or_default();
+        let date = date_column.and_then(|date_column| record.get(date_column));
+        let result = match convert_row(&mut lookup, amount, currency, date, rounding_mode).await {
+            Ok((converted, rate)) => {
+                summary.converted += 1;
+                [target.clone(), converted.to_string(), rate.display_rate().to_string(), rate.time_last_update_utc, String::new()]
+            }
+            Err(e) => {
+                summary.failed += 1;
+                [target.clone(), String::new(), String::new(), String::new(), e.to_string()]
+            }
+        };
+        let output_record = record.iter().chain(result.iter().map(String::as_str)).collect::<csv::StringRecord>();
+        if writer.write_record(&output_record).is_err() {
+            return Err(BatchError::WriteError.into());
+        }
+    }
+    match writer.flush() {
+        Ok(_) => Ok(summary),
+        Err(_) => Err(BatchError::WriteError.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use chrono::NaiveDate;
+    use super::convert_batch;
+    use crate::cache::CachedProvider;
+    use crate::ecb::{parse_reference_rates, reference_rates_on, Ecb, BASE_ECB_URL};
+    use crate::retry::RetryPolicy;
+    use crate::engine::RoundingMode;
+    use crate::test_support::{temp_path, DAILY_XML, HISTORY_XML};
+
+
+    // An offline ECB provider with the recorded reference rates in its cache, as the EUR tables it fetches, so nothing is requested.
+    fn offline_provider(name: &str) -> (CachedProvider, PathBuf) {
+        let cache_dir = temp_path(&format!("batch-{}", name));
+        fs::create_dir_all(cache_dir.join("history")).unwrap();
+        let latest = &parse_reference_rates(DAILY_XML, "EUR").unwrap()[0];
+        fs::write(cache_dir.join("ecb-EUR.json"), serde_json::to_string(latest).unwrap()).unwrap();
+        let history = reference_rates_on(HISTORY_XML, "EUR", NaiveDate::from_ymd_opt(2024, 3, 14).unwrap()).unwrap();
+        fs::write(cache_dir.join("history").join("ecb-EUR-2024-03-14.json"), serde_json::to_string(&history).unwrap()).unwrap();
+        (CachedProvider::new(Box::new(Ecb::new(BASE_ECB_URL, RetryPolicy::default()).unwrap()), cache_dir.clone(), true), cache_dir)
+    }
+
+    async fn run_batch(provider: &CachedProvider, input: &str, default_date: Option<NaiveDate>) -> (String, usize, usize) {
+        let mut output = Vec::new();
+        let summary = convert_batch(provider, input.as_bytes(), &mut output, b',', "eur", default_date, RoundingMode::HalfEven).await.unwrap();
+        (String::from_utf8(output).unwrap(), summary.converted, summary.failed)
+    }
+
+    #[tokio::test]
+    async fn test_convert_batch() {
+        let (provider, cache_dir) = offline_provider("rows");
+        let input = "Description,Amount,Currency,Date\nHotel,100,USD,\nTaxi,12.5,usd,2024-03-14\nLunch,abc,USD,\nTrain,20,GBX,\nDinner,30,USD,14.03.2024\n";
+        let (output, converted, failed) = run_batch(&provider, input, None).await;
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "Description,Amount,Currency,Date,target_currency,converted_amount,rate,rate_time_utc,error");
+        assert_eq!(lines[1], "Hotel,100,USD,,EUR,91.81,0.9181050312,\"Fri, 15 Mar 2024 15:00:00 +0000\",");
+        assert_eq!(lines[2], "Taxi,12.5,usd,2024-03-14,EUR,11.44,0.9153318078,\"Thu, 14 Mar 2024 15:00:00 +0000\",");
+        assert_eq!(lines[3], "Lunch,abc,USD,,EUR,,,,Invalid amount: abc.");
+        assert!(lines[4].starts_with("Train,20,GBX,,EUR,,,,"));
+        assert_eq!(lines[5], "Dinner,30,USD,14.03.2024,EUR,,,,Invalid date: 14.03.2024. Please use the YYYY-MM-DD format.");
+        assert_eq!((converted, failed), (2, 3));
+        let _ = fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_batch_default_date() {
+        let (provider, cache_dir) = offline_provider("date");
+        let date = NaiveDate::from_ymd_opt(2024, 3, 14);
+        let (output, converted, failed) = run_batch(&provider, "amount,currency\n1000.05,USD\n", date).await;
+        assert_eq!(output.lines().nth(1), Some("1000.05,USD,EUR,915.38,0.9153318078,\"Thu, 14 Mar 2024 15:00:00 +0000\","));
+        assert_eq!((converted, failed), (1, 0));
+        let mut output = Vec::new();
+        let result = convert_batch(&provider, "value,code\n1,USD\n".as_bytes(), &mut output, b',', "EUR", None, RoundingMode::HalfEven).await;
+        assert_eq!(result.err().unwrap().to_string(), "The input file has no amount column.");
+        let _ = fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_target() {
+        let (provider, cache_dir) = offline_provider("target");
+        let mut output = Vec::new();
+        let result = convert_batch(&provider, "amount,currency\n1,USD\n2,USD\n".as_bytes(), &mut output, b',', "EUX", None, RoundingMode::HalfEven).await;
+        assert!(result.is_err());
+        assert!(output.is_empty());
+        let _ = fs::remove_dir_all(cache_dir);
+    }
+}
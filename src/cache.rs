@@ -0,0 +1,616 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::alerts::{alerts_path, check_alerts};
+use crate::api::{ApiEnrichedResponse, ApiMultirateResponse, ApiRateResponse};
+use crate::config::get_cache_dir;
+use crate::engine::{derive_rate, direct_rate, table_contains, DerivedRate};
+use crate::provider::{pair_from_table, provider_from_config, RateProvider};
+use crate::store::{store_path, RateStore};
+use crate::usage::{count_request, usage_path};
+
+// Tables of all rates are stored in the cache directory and reused until the provider's next update time.
+// Pairs and conversions are derived locally from any cached table containing both currencies,
+// and when there is none the provider's reference table is fetched, so one request serves all pairs until the next update.
+// Historical tables never change, they are stored in a separate directory and are always reused.
+// The list of supported currencies rarely changes, it is reused for a week.
+
+#[derive(Error, Debug)]
+enum CacheError {
+    #[error("No cached exchange rates for {0}. Run the command without --offline first.")]
+    NotCached(String),
+    #[error("No cached list of supported currencies. Run the command without --offline first.")]
+    CodesNotCached,
+    #[error("{0} is not supported by {1}. Type codes for a list of the supported currencies.")]
+    UnsupportedCode(String, String),
+    #[error("Currency details are not available offline.")]
+    DetailsOffline,
+}
+
+const CODES_MAX_AGE: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct CachedCodes {
+    time_fetched_unix: i64,
+    supported_codes: Vec<(String, String)>,
+}
+
+pub struct CachedProvider {
+    inner: Box<dyn RateProvider>,
+    cache_dir: PathBuf,
+    // In offline mode the last cached table is used no matter how old it is, and the network is never used.
+    offline: bool,
+    // Database every fetched table is added to, see store.rs.
+    store_path: Option<PathBuf>,
+    // Alerts checked with every fetched table, see alerts.rs.
+    alerts_path: Option<PathBuf>,
+    // Request counts of every provider, see usage.rs.
+    usage_path: Option<PathBuf>,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Box<dyn RateProvider>, cache_dir: PathBuf, offline: bool) -> CachedProvider {
+        CachedProvider { inner, cache_dir, offline, store_path: None, alerts_path: None, usage_path: None }
+    }
+
+    pub fn from_config(inner: Box<dyn RateProvider>, offline: bool) -> Result<CachedProvider> {
+        let cache_dir = PathBuf::from(get_cache_dir()?).join("rates");
+        let mut provider = CachedProvider::new(inner, cache_dir, offline);
+        provider.store_path = Some(store_path()?);
+        provider.alerts_path = Some(alerts_path()?);
+        provider.usage_path = Some(usage_path()?);
+        Ok(provider)
+    }
+
+    // Called before every request to the provider. A request that isn't counted only makes the quota estimate lower.
+    fn count_request(&self) {
+        if let Some(path) = &self.usage_path {
+            let _ = count_request(path, self.inner.name());
+        }
+    }
+
+    // Failing to store the table only means it will be missing from the local history.
+    fn store_table(&self, table: &ApiMultirateResponse) {
+        if let Some(path) = &self.store_path {
+            if let Ok(mut store) = RateStore::open(path) {
+                let _ = store.save(self.inner.name(), table);
+            }
+        }
+    }
+
+    fn table_path(&self, base_currency: &str) -> Option<PathBuf> {
+        if base_currency.is_empty() || !base_currency.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let file_name = format!("{}-{}.json", self.inner.name(), base_currency.to_uppercase());
+        Some(self.cache_dir.join(file_name))
+    }
+
+    fn history_path(&self, base_currency: &str, date: NaiveDate) -> Option<PathBuf> {
+        let file_name = self.table_path(base_currency)?.file_stem()?.to_string_lossy().to_string();
+        Some(self.cache_dir.join("history").join(format!("{}-{}.json", file_name, date)))
+    }
+
+    fn read_history_table(&self, base_currency: &str, date: NaiveDate) -> Option<ApiMultirateResponse> {
+        let table_json = fs::read_to_string(self.history_path(base_currency, date)?).ok()?;
+        serde_json::from_str(&table_json).ok()
+    }
+
+    fn write_history_table(&self, table: &ApiMultirateResponse, date: NaiveDate) -> Result<()> {
+        if let Some(path) = self.history_path(&table.base_code, date) {
+            fs::create_dir_all(self.cache_dir.join("history"))?;
+            fs::write(path, serde_json::to_string(table)?)?;
+        }
+        Ok(())
+    }
+
+    fn read_table(&self, base_currency: &str) -> Option<ApiMultirateResponse> {
+        let table_json = fs::read_to_string(self.table_path(base_currency)?).ok()?;
+        serde_json::from_str(&table_json).ok()
+    }
+
+    // All cached tables of this provider, the ones with the preferred bases first.
+    fn cached_tables(&self, preferred_bases: &[&str]) -> Vec<ApiMultirateResponse> {
+        let prefix = format!("{}-", self.inner.name());
+        let mut tables = match fs::read_dir(&self.cache_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+                .filter_map(|table_json| serde_json::from_str::<ApiMultirateResponse>(&table_json).ok())
+                .collect::<Vec<ApiMultirateResponse>>(),
+            Err(_) => Vec::new(),
+        };
+        tables.sort_by_key(|table| {
+            preferred_bases
+                .iter()
+                .position(|base| base.eq_ignore_ascii_case(&table.base_code))
+                .unwrap_or(preferred_bases.len())
+        });
+        tables
+    }
+
+    // A table without any valid rate is never used.
+    fn is_usable(&self, table: &ApiMultirateResponse) -> bool {
+        !table.conversion_rates.is_empty() && (self.offline || table.time_next_update_unix > unix_now())
+    }
+
+    // Table both currencies can be derived from, a cached one when possible.
+    pub async fn table_for_pair(&self, from: &str, to: &str) -> Result<ApiMultirateResponse> {
+        let reference = self.inner.reference_currency();
+        let cached = self
+            .cached_tables(&[from, to, reference])
+            .into_iter()
+            .find(|table| self.is_usable(table) && table_contains(table, from) && table_contains(table, to));
+        match cached {
+            Some(table) => Ok(table),
+            None if self.offline => Err(CacheError::NotCached(from.to_uppercase()).into()),
+            None => self.latest(reference).await,
+        }
+    }
+
+    pub async fn derived_rate(&self, from: &str, to: &str) -> Result<DerivedRate> {
+        let table = self.table_for_pair(from, to).await?;
+        if self.offline || (table_contains(&table, from) && table_contains(&table, to)) {
+            return derive_rate(&table, from, to);
+        }
+        // The reference table doesn't list one of the currencies, so ask the provider for the pair itself.
+        self.count_request();
+        let response = self.inner.pair(from, to).await?;
+        Ok(direct_rate(response))
+    }
+
+    // Same as derived_rate, with the rates published on the given date.
+    pub async fn derived_history_rate(&self, from: &str, to: &str, date: NaiveDate) -> Result<DerivedRate> {
+        let reference = self.inner.reference_currency();
+        let cached = [from, to, reference]
+            .iter()
+            .filter_map(|base| self.read_history_table(base, date))
+            .find(|table| table_contains(table, from) && table_contains(table, to));
+        let table = match cached {
+            Some(table) => table,
+            None if self.offline => return Err(CacheError::NotCached(from.to_uppercase()).into()),
+            None => self.history(reference, date).await?,
+        };
+        if table_contains(&table, from) && table_contains(&table, to) {
+            return derive_rate(&table, from, to);
+        }
+        let table = self.history(from, date).await?;
+        derive_rate(&table, from, to)
+    }
+
+    fn codes_path(&self) -> PathBuf {
+        self.cache_dir.join("codes").join(format!("{}.json", self.inner.name()))
+    }
+
+    fn read_codes(&self) -> Option<CachedCodes> {
+        let codes_json = fs::read_to_string(self.codes_path()).ok()?;
+        serde_json::from_str(&codes_json).ok()
+    }
+
+    fn write_codes(&self, codes: &CachedCodes) -> Result<()> {
+        fs::create_dir_all(self.cache_dir.join("codes"))?;
+        fs::write(self.codes_path(), serde_json::to_string(codes)?)?;
+        Ok(())
+    }
+
+    // Checks the codes against the provider's supported currencies before asking for rates.
+    // When the list can't be loaded (e.g. offline with nothing cached), the provider is left to reject them.
+    pub async fn check_supported(&self, codes: &[&str]) -> Result<()> {
+        let supported_codes = match self.supported_codes().await {
+            Ok(supported_codes) if !supported_codes.is_empty() => supported_codes,
+            _ => return Ok(()),
+        };
+        for code in codes {
+            if !supported_codes.iter().any(|(supported, _)| supported.eq_ignore_ascii_case(code)) {
+                return Err(CacheError::UnsupportedCode(code.to_uppercase(), self.inner.name().to_string()).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn write_table(&self, table: &ApiMultirateResponse) -> Result<()> {
+        if let Some(path) = self.table_path(&table.base_code) {
+            fs::create_dir_all(&self.cache_dir)?;
+            fs::write(path, serde_json::to_string(table)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse> {
+        if let Some(table) = self.read_table(base_currency) {
+            if self.is_usable(&table) {
+                return Ok(table);
+            }
+        }
+        if self.offline {
+            return Err(CacheError::NotCached(base_currency.to_string()).into());
+        }
+        self.count_request();
+        let table = self.inner.latest(base_currency).await?;
+        // Failing to write the cache only means the rates will be requested again next time.
+        let _ = self.write_table(&table);
+        self.store_table(&table);
+        if let Some(path) = &self.alerts_path {
+            // Unreadable alerts shouldn't stop the command that fetched the rates.
+            let _ = check_alerts(path, &table).await;
+        }
+        Ok(table)
+    }
+
+    fn reference_currency(&self) -> &'static str {
+        self.inner.reference_currency()
+    }
+
+    async fn pair(&self, from: &str, to: &str) -> Result<ApiRateResponse> {
+        let table = self.table_for_pair(from, to).await?;
+        pair_from_table(table, from, to)
+    }
+
+    async fn history(&self, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse> {
+        if let Some(table) = self.read_history_table(base_currency, date) {
+            return Ok(table);
+        }
+        if self.offline {
+            return Err(CacheError::NotCached(base_currency.to_string()).into());
+        }
+        self.count_request();
+        let table = self.inner.history(base_currency, date).await?;
+        let _ = self.write_history_table(&table, date);
+        self.store_table(&table);
+        Ok(table)
+    }
+
+    async fn supported_codes(&self) -> Result<Vec<(String, String)>> {
+        if let Some(codes) = self.read_codes() {
+            if self.offline || unix_now() - codes.time_fetched_unix < CODES_MAX_AGE {
+                return Ok(codes.supported_codes);
+            }
+        }
+        if self.offline {
+            return Err(CacheError::CodesNotCached.into());
+        }
+        self.count_request();
+        let supported_codes = self.inner.supported_codes().await?;
+        let _ = self.write_codes(&CachedCodes { time_fetched_unix: unix_now(), supported_codes: supported_codes.clone() });
+        Ok(supported_codes)
+    }
+
+    // Not cached, the details are only requested by rate --details.
+    async fn enriched(&self, from: &str, to: &str) -> Result<ApiEnrichedResponse> {
+        if self.offline {
+            return Err(CacheError::DetailsOffline.into());
+        }
+        self.count_request();
+        self.inner.enriched(from, to).await
+    }
+}
+
+pub fn cached_provider_from_config(offline: bool) -> Result<CachedProvider> {
+    let provider = provider_from_config()?;
+    CachedProvider::from_config(provider, offline)
+}
+
+pub fn unix_now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+pub fn format_age(seconds: i64) -> String {
+    let (value, unit) = match seconds.max(0) {
+        s if s < 60 => (s, "second"),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 60 * 60 * 24 => (s / (60 * 60), "hour"),
+        s => (s / (60 * 60 * 24), "day"),
+    };
+    if value == 1 {
+        format!("{} {}", value, unit)
+    } else {
+        format!("{} {}s", value, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use chrono::NaiveDate;
+    use super::{format_age, unix_now, CachedProvider};
+    use crate::api::{ApiHistoryResponse, ApiMultirateResponse, ApiRateResponse, RequestError};
+    use crate::provider::RateProvider;
+    use crate::test_support::{decimal, temp_path, HISTORY_USD, LATEST_USD};
+
+
+    // Serves the recorded USD table, with the next update `next_update_in` seconds from now.
+    struct CountingProvider {
+        calls: Arc<AtomicUsize>,
+        next_update_in: i64,
+    }
+
+    #[async_trait]
+    impl RateProvider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !base_currency.eq_ignore_ascii_case("USD") {
+                return Err(RequestError::UnsupportedCurrency(None).into());
+            }
+            let mut table: ApiMultirateResponse = serde_json::from_str(LATEST_USD)?;
+            table.time_next_update_unix = unix_now() + self.next_update_in;
+            Ok(table)
+        }
+
+        // Only called for currencies missing from the table, which the API doesn't support either.
+        async fn pair(&self, _from: &str, _to: &str) -> Result<ApiRateResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(RequestError::UnsupportedCurrency(None).into())
+        }
+
+        // Only the recorded USD table of 2024-03-14 is available.
+        async fn history(&self, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !base_currency.eq_ignore_ascii_case("USD") || date != history_date() {
+                return Err(RequestError::NoDataAvailable(None).into());
+            }
+            serde_json::from_str::<ApiHistoryResponse>(HISTORY_USD)?.into_table()
+        }
+
+        async fn supported_codes(&self) -> Result<Vec<(String, String)>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![("USD".to_string(), "United States Dollar".to_string()), ("EUR".to_string(), "Euro".to_string())])
+        }
+    }
+
+    fn history_date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 14).unwrap()
+    }
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        temp_path(&format!("cache-{}", name))
+    }
+
+    fn cached(cache_dir: &Path, next_update_in: i64, offline: bool) -> (CachedProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = CountingProvider { calls: calls.clone(), next_update_in };
+        (CachedProvider::new(Box::new(inner), cache_dir.to_path_buf(), offline), calls)
+    }
+
+    #[tokio::test]
+    async fn test_fresh_table_served_from_cache() {
+        let cache_dir = temp_cache_dir("fresh");
+        let (provider, calls) = cached(&cache_dir, 3600, false);
+        provider.latest("USD").await.unwrap();
+        let rate = provider.pair("USD", "EUR").await.unwrap();
+        let conversion = provider.derived_rate("usd", "GBP").await.unwrap();
+        assert_eq!(rate.conversion_rate, decimal("0.9181"));
+        assert_eq!(conversion.to, "GBP");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_cross_rates_from_one_request() {
+        let cache_dir = temp_cache_dir("cross");
+        let (provider, calls) = cached(&cache_dir, 3600, false);
+        let eur_jpy = provider.derived_rate("EUR", "JPY").await.unwrap();
+        let gbp_usd = provider.derived_rate("GBP", "USD").await.unwrap();
+        let chf_pln = provider.pair("CHF", "PLN").await.unwrap();
+        assert_eq!(eur_jpy.path_description(), "EUR -> USD -> JPY");
+        assert_eq!(gbp_usd.path_description(), "GBP -> USD");
+        assert_eq!(chf_pln.base_code, "CHF");
+        assert_eq!(chf_pln.conversion_rate, decimal("3.9417") / decimal("0.8837"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_expired_table_fetched_again() {
+        let cache_dir = temp_cache_dir("expired");
+        let (provider, calls) = cached(&cache_dir, -60, false);
+        provider.latest("USD").await.unwrap();
+        provider.pair("USD", "EUR").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_offline_uses_expired_table() {
+        let cache_dir = temp_cache_dir("offline");
+        let (online, _) = cached(&cache_dir, -60, false);
+        online.latest("USD").await.unwrap();
+        let (offline, calls) = cached(&cache_dir, -60, true);
+        let rate = offline.pair("USD", "EUR").await.unwrap();
+        assert_eq!(rate.time_last_update_unix, 1710460801);
+        let cross = offline.derived_rate("EUR", "GBP").await.unwrap();
+        assert!(cross.is_triangulated());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        match offline.latest("EUR").await {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "No cached exchange rates for EUR. Run the command without --offline first."),
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_all_correct() {
+        let cache_dir = temp_cache_dir("convert-all-correct");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_rate("USD", "EUR").await {
+            Ok(rate) => {
+                assert_eq!(rate.from, "USD");
+                assert_eq!(rate.to, "EUR");
+                assert_eq!(rate.convert(decimal("100")).unwrap(), decimal("91.81"));
+            }
+            Err(e) => {
+                panic!("Error converting currency: {}", e);
+            }
+        }
+
+        match provider.derived_rate("USD", "EUR").await {
+            Ok(rate) => {
+                assert_eq!(rate.from, "USD");
+                assert_eq!(rate.to, "EUR");
+                assert_eq!(rate.convert(decimal("100.0")).unwrap(), decimal("91.81"));
+            }
+            Err(e) => {
+                panic!("Error converting currency: {}", e);
+            }
+        }
+
+        match provider.derived_rate("USD", "EUR").await {
+            Ok(rate) => {
+                assert_eq!(rate.from, "USD");
+                assert_eq!(rate.to, "EUR");
+                assert_eq!(rate.convert(decimal("4231.1296")).unwrap(), decimal("3884.60008576"));
+            }
+            Err(e) => {
+                panic!("Error converting currency: {}", e);
+            }
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_wrong_left_currency() {
+        let cache_dir = temp_cache_dir("convert-wrong-left-currency");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_rate("UST", "EUR").await {
+            Ok(_) => {
+                panic!("Expected an error, but got a response.");
+            }
+            Err(e) => {
+                assert_eq!(e.to_string(), "Unsupported currency.");
+            }
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_wrong_right_currency() {
+        let cache_dir = temp_cache_dir("convert-wrong-right-currency");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_rate("USD", "EUX").await {
+            Ok(_) => {
+                panic!("Expected an error, but got a response.");
+            }
+            Err(e) => {
+                assert_eq!(e.to_string(), "Unsupported currency.");
+            }
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_small_amount() {
+        let cache_dir = temp_cache_dir("convert-small-amount");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_rate("USD", "EUR").await {
+            Ok(rate) => {
+                assert_eq!(rate.from, "USD");
+                assert_eq!(rate.to, "EUR");
+                assert_eq!(rate.convert(decimal("0.00025")).unwrap(), decimal("0.000229525"));
+            }
+            Err(e) => {
+                panic!("Error converting currency: {}", e);
+            }
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_convert_big_amount() {
+        let cache_dir = temp_cache_dir("convert-big-amount");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_rate("USD", "EUR").await {
+            Ok(rate) => {
+                assert_eq!(rate.from, "USD");
+                assert_eq!(rate.to, "EUR");
+                assert_eq!(rate.convert(decimal("326235234543.32452362323")).unwrap(), decimal("299516568834.226245138487463"));
+            }
+            Err(e) => {
+                panic!("Error converting currency: {}", e);
+            }
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_history_cached() {
+        let cache_dir = temp_cache_dir("history");
+        let (provider, calls) = cached(&cache_dir, 3600, false);
+        let rate = provider.derived_history_rate("EUR", "PLN", history_date()).await.unwrap();
+        assert_eq!(rate.rate, decimal("3.9212") / decimal("0.9153"));
+        assert_eq!(rate.time_last_update_utc, "Thu, 14 Mar 2024 00:00:00 +0000");
+        provider.derived_history_rate("USD", "JPY", history_date()).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // The latest table is stored separately and isn't affected.
+        let latest = provider.derived_rate("EUR", "PLN").await.unwrap();
+        assert_eq!(latest.rate, decimal("3.9417") / decimal("0.9181"));
+        let (offline, calls) = cached(&cache_dir, 3600, true);
+        offline.derived_history_rate("GBP", "CHF", history_date()).await.unwrap();
+        assert!(offline.derived_history_rate("GBP", "CHF", NaiveDate::from_ymd_opt(2024, 3, 13).unwrap()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_history_not_available() {
+        let cache_dir = temp_cache_dir("history-not-available");
+        let (provider, _) = cached(&cache_dir, 3600, false);
+        match provider.derived_history_rate("USD", "EUR", NaiveDate::from_ymd_opt(1980, 1, 2).unwrap()).await {
+            Ok(_) => panic!("Expected an error, but got a rate."),
+            Err(e) => assert_eq!(e.to_string(), "No data available for the date."),
+        }
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_supported_codes_cached() {
+        let cache_dir = temp_cache_dir("codes");
+        let (offline_provider, _) = cached(&cache_dir, 3600, true);
+        match offline_provider.supported_codes().await {
+            Ok(_) => panic!("Expected an error, but got the codes."),
+            Err(e) => assert_eq!(e.to_string(), "No cached list of supported currencies. Run the command without --offline first."),
+        }
+        // Nothing to check against, so the provider decides.
+        assert!(offline_provider.check_supported(&["XAU"]).await.is_ok());
+        let (provider, calls) = cached(&cache_dir, 3600, false);
+        assert_eq!(provider.supported_codes().await.unwrap().len(), 2);
+        assert!(provider.check_supported(&["usd", "EUR"]).await.is_ok());
+        match provider.check_supported(&["USD", "gbp"]).await {
+            Ok(_) => panic!("Expected an error, but the code was accepted."),
+            Err(e) => assert_eq!(e.to_string(), "GBP is not supported by counting. Type codes for a list of the supported currencies."),
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(offline_provider.supported_codes().await.unwrap()[1].0, "EUR");
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(1), "1 second");
+        assert_eq!(format_age(150), "2 minutes");
+        assert_eq!(format_age(60 * 60), "1 hour");
+        assert_eq!(format_age(60 * 60 * 24 * 3 + 5), "3 days");
+    }
+}
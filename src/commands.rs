@@ -1,15 +1,98 @@
-use crate::{api::{get_all_exchange_rates, get_exchange_rate, convert}, config::{write_config, remove_config, read_config}};
-use std::process::exit;  
+use crate::{cache::{cached_provider_from_config, format_age, unix_now}, provider::{check_provider_name, RateProvider, provider_from_config, provider_name_from_config, DEFAULT_PROVIDER, PROVIDERS}, config::{set_api_key, remove_api_key, read_api_key, set_provider, set_alert_command, set_alert_webhook, set_quota_warning_thresholds}};
+use crate::alerts::{add_alert, alerts_path, parse_condition, parse_pair, read_alerts, remove_alert};
+use crate::api::ApiMultirateResponse;
+use crate::batch::convert_batch;
+use crate::expr::parse_expression;
+use crate::phrase::{parse_phrase, Phrase};
+use crate::currency::{find_currency, validate_currency};
+use crate::cache::CachedProvider;
+use crate::engine::{derive_rate, table_contains, DerivedRate, RoundingMode, RATE_DISPLAY_DECIMALS};
+use crate::store::RateStore;
+use crate::usage::{parse_thresholds, read_usage, refresh_quota, today, usage_path, warning_thresholds, QuotaEstimate, QUOTA_PROVIDER};
+use crate::watch::{parse_interval, watch_rate, RateChange, DEFAULT_INTERVAL};
+use crate::output::{decimal_json, error_json, print_json, write_rate_table, OutputFormat};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::stdout;
+use std::process::exit;
+use std::str::FromStr;
+use std::time::Duration;
 
 enum Command {
     Help,
     GetAllRates,
     GetRate,
     Convert,
+    Batch,
+    Calc,
+    Codes,
+    History,
+    Store,
+    Watch,
+    Alert,
+    Info,
     Key,
+    Provider,
+    Quota,
     Exit,
 }
 
+// Outcome of a command. When a single command is run from the command line, it becomes the exit code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandStatus {
+    Success,
+    // The command was valid, but failed (e.g. a request error or a missing API key).
+    Failure,
+    // The command or its arguments were not recognized.
+    UsageError,
+}
+
+impl CommandStatus {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CommandStatus::Success => 0,
+            CommandStatus::Failure => 1,
+            CommandStatus::UsageError => 2,
+        }
+    }
+}
+
+// Options that can be added to any command.
+#[derive(Clone)]
+struct Options {
+    offline: bool,
+    rounding_mode: RoundingMode,
+    format: OutputFormat,
+    // File the rate table or the batch conversion is written to instead of the standard output.
+    output_file: Option<String>,
+    // Date of historical rates, the latest rates are used when not set.
+    date: Option<NaiveDate>,
+    // How often the rate is checked by watch.
+    interval: Duration,
+}
+
+impl Options {
+    // Progress messages and prose are only printed in the text format.
+    fn text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    fn json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+}
+
+const INVALID_DATE: &str = "Invalid date provided. Please provide a date in the YYYY-MM-DD format.";
+
+// Completed with Tab in the interactive mode.
+pub const COMMAND_NAMES: [&str; 18] = [
+    "help", "all", "rates", "list", "rate", "convert", "batch", "calc", "codes", "history", "store", "watch", "alert", "info", "key", "provider", "quota", "exit",
+];
+
 fn match_command(input: &str) -> Option<Command> {
     match input {
         "help" => Some(Command::Help), // "help" is the command to get a list of commands
@@ -18,149 +101,1232 @@ fn match_command(input: &str) -> Option<Command> {
         "list" => Some(Command::GetAllRates), // "list" is an alias for "all"
         "rate" => Some(Command::GetRate), // "rate" is the command to get a specific exchange rate
         "convert" => Some(Command::Convert), // "convert" is the command to convert an amount from one currency to another
+        "batch" => Some(Command::Batch), // "batch" is the command to convert every amount in a CSV file
+        "calc" => Some(Command::Calc), // "calc" is the command to calculate with amounts in different currencies
+        "codes" => Some(Command::Codes), // "codes" is the command to list the currencies supported by the provider
+        "history" => Some(Command::History), // "history" is the command to get the exchange rates of a past date
+        "store" => Some(Command::Store), // "store" is the command to query the exchange rates stored locally
+        "watch" => Some(Command::Watch), // "watch" is the command to follow the exchange rate between two currencies
+        "alert" => Some(Command::Alert), // "alert" is the command to manage alerts on exchange rate movements
+        "info" => Some(Command::Info), // "info" is the command to show details about a currency
         "key" => Some(Command::Key), // "key" is the command to set the API key
+        "provider" => Some(Command::Provider), // "provider" is the command to choose the exchange rate service
+        "quota" => Some(Command::Quota), // "quota" is the command to check how many requests are left
         "exit" => Some(Command::Exit), // "exit" is the command to exit the program
         _ => None,
     }
 }
 
-pub async fn new_command(command: &str, args: Option<Vec<&str>>) {
-    let command = match_command(command);
-    let args = args.unwrap_or_default();
+// Removes the flag from the arguments, returns whether it was present.
+fn take_flag(args: &mut Vec<&str>, flag: &str) -> bool {
+    let length = args.len();
+    args.retain(|arg| *arg != flag);
+    args.len() != length
+}
+
+// Removes the option and its value from the arguments, returns the value if the option was present.
+fn take_option<'a>(args: &mut Vec<&'a str>, option: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| *arg == option)?;
+    args.remove(position);
+    if position < args.len() {
+        Some(args.remove(position))
+    } else {
+        Some("")
+    }
+}
+
+// Prints an invalid command or argument, with an example of the correct usage.
+fn usage_error(options: &Options, message: &str, example: Option<&str>) -> CommandStatus {
+    if options.json() {
+        print_json(&error_json(&anyhow!("{}", message), true));
+    } else {
+        println!("{}", message);
+        if let Some(example) = example {
+            println!("[Example: {}]", example);
+        }
+    }
+    CommandStatus::UsageError
+}
+
+// For commands that only change a setting.
+fn report_success(options: &Options, message: &str) -> CommandStatus {
+    if options.json() {
+        print_json(&json!({ "result": "success", "message": message }));
+    } else {
+        println!("{}", message);
+    }
+    CommandStatus::Success
+}
+
+fn report_error(options: &Options, context: &str, error: anyhow::Error, status: CommandStatus) -> CommandStatus {
+    if options.json() {
+        print_json(&error_json(&error, status == CommandStatus::UsageError));
+    } else {
+        println!("{}: {}", context, error);
+    }
+    status
+}
+
+fn rate_json(rate: &DerivedRate, date: Option<NaiveDate>) -> Value {
+    let mut rate_json = json!({
+        "from": rate.from,
+        "to": rate.to,
+        "rate": decimal_json(rate.display_rate()),
+        "path": rate.path,
+        "time_last_update_unix": rate.time_last_update_unix,
+        "time_last_update_utc": rate.time_last_update_utc,
+    });
+    if let Some(date) = date {
+        rate_json["date"] = json!(date.to_string());
+    }
+    rate_json
+}
+
+fn print_staleness(time_last_update_unix: i64, time_last_update_utc: &str) {
+    let age = format_age(unix_now() - time_last_update_unix);
+    println!("Offline mode: using cached rates last updated {} ({} ago).", time_last_update_utc, age);
+}
+
+// Historical rates don't get stale, for them the publication time is shown instead.
+fn print_rates_time(options: &Options, time_last_update_unix: i64, time_last_update_utc: &str) {
+    if let Some(date) = options.date {
+        println!("Rates for {}, published {}.", date, time_last_update_utc);
+    } else if options.offline {
+        print_staleness(time_last_update_unix, time_last_update_utc);
+    }
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+async fn rate_on_date(provider: &CachedProvider, from: &str, to: &str, options: &Options) -> Result<DerivedRate> {
+    match options.date {
+        Some(date) => provider.derived_history_rate(from, to, date).await,
+        None => provider.derived_rate(from, to).await,
+    }
+}
+
+pub async fn new_command(input: &str, args: Option<Vec<&str>>) -> CommandStatus {
+    let command = match_command(input);
+    let mut args = args.unwrap_or_default();
+    let offline = take_flag(&mut args, "--offline");
+    let output_file = take_option(&mut args, "--output").map(String::from);
+    let mut options = Options { offline, rounding_mode: RoundingMode::HalfEven, format: OutputFormat::Text, output_file, date: None, interval: DEFAULT_INTERVAL };
+    // An invalid format is reported in text, the format that was asked for isn't known.
+    match take_option(&mut args, "--format").map(OutputFormat::from_str) {
+        Some(Ok(format)) => options.format = format,
+        Some(Err(e)) => return usage_error(&options, &e.to_string(), Some("rate USD EUR --format json")),
+        None => (),
+    }
+    match take_option(&mut args, "--round").map(RoundingMode::from_str) {
+        Some(Ok(mode)) => options.rounding_mode = mode,
+        Some(Err(e)) => return usage_error(&options, &e.to_string(), None),
+        None => (),
+    }
+    match take_option(&mut args, "--interval").map(parse_interval) {
+        Some(Ok(interval)) => options.interval = interval,
+        Some(Err(e)) => return usage_error(&options, &e.to_string(), None),
+        None => (),
+    }
+    match take_option(&mut args, "--date").map(parse_date) {
+        Some(Some(date)) => options.date = Some(date),
+        Some(None) => return usage_error(&options, INVALID_DATE, None),
+        None => (),
+    }
+    if options.format.delimiter().is_some() && !matches!(command, Some(Command::GetAllRates) | Some(Command::History) | Some(Command::Batch)) {
+        return usage_error(&options, "CSV and TSV output is only available for the all, history and batch commands.", Some("all USD --format csv"));
+    }
+    // Batch conversions are always written as a table, CSV by default.
+    if options.output_file.is_some() && options.format.delimiter().is_none() && !matches!(command, Some(Command::Batch)) {
+        return usage_error(&options, "--output can only be used with --format csv or --format tsv.", Some("all USD --format csv --output rates.csv"));
+    }
     match command {
-        Some(Command::Help) => {
-            println!("==== Help ====");
-            println!("Available commands:");
-            println!("help - Get a list of commands");
-            println!("all [BASE_CURRENCY] - Get all exchange rates for base currency (default is USD)");
-            println!("rate [CURRENCY_1] [CURRENCY_2] - Get the exchange rate between two currencies");
-            println!("convert [CURRENCY_FROM] [CURRENCY_TO] [AMOUNT] - Convert an amount from one currency to another");
-            println!("key [view/set/remove] [API_KEY] - View, set, or remove the API key");
-            println!("exit - Exit the program");
-            println!("==============");
-        }
-        Some(Command::GetAllRates) => {
-            let base_currency = if args.is_empty() {
-                println!("Base currency not provided. Using USD as the base currency.");
-                "USD"
-            } else {
-                args[0]
-            };
-            println!("Getting all exchange rates for {}...", base_currency);
-            let exchange_rate_response = get_all_exchange_rates(base_currency).await;
-            match exchange_rate_response {
-                Ok(response) => {
-                    println!("Exchange rates for {}:", response.base_code);
-                    for (currency, rate) in response.conversion_rates.as_object().unwrap() {
-                        println!("{}: {}", currency, rate);
+        Some(Command::Help) => help(),
+        Some(Command::GetAllRates) => get_all_rates(&args, &options).await,
+        Some(Command::GetRate) => get_rate(&args, &options).await,
+        Some(Command::Convert) => convert(&args, &options).await,
+        Some(Command::Batch) => batch(&args, &options).await,
+        Some(Command::Calc) => calc(&args, &options).await,
+        Some(Command::Codes) => codes(&args, &options).await,
+        Some(Command::History) => history(&args, &options).await,
+        Some(Command::Store) => store(&args, &options),
+        Some(Command::Watch) => watch(&args, &options).await,
+        Some(Command::Alert) => alert(&args, &options).await,
+        Some(Command::Info) => info(&args, &options),
+        Some(Command::Key) => key(&args, &options),
+        Some(Command::Provider) => provider(&args, &options).await,
+        Some(Command::Quota) => quota(&args, &options).await,
+        Some(Command::Exit) => {
+            println!("Exiting the program...");
+            exit(0);
+        }
+        None => phrase(input, &args, &options).await,
+    }
+}
+
+// Lists the currencies the provider supports, only the ones whose code or name contains the search when given.
+async fn codes(args: &[&str], options: &Options) -> CommandStatus {
+    let search = args.join(" ").to_lowercase();
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error getting supported currencies", e, CommandStatus::Failure),
+    };
+    if options.text() {
+        println!("Getting currencies supported by {}...", provider.name());
+    }
+    let codes = match provider.supported_codes().await {
+        Ok(codes) => codes,
+        Err(e) => return report_error(options, "Error getting supported currencies", e, CommandStatus::Failure),
+    };
+    let matching = codes
+        .iter()
+        .filter(|(code, name)| code.to_lowercase().contains(&search) || name.to_lowercase().contains(&search))
+        .collect::<Vec<&(String, String)>>();
+    if options.json() {
+        let codes = matching.iter().map(|(code, name)| json!({ "code": code, "name": name })).collect::<Vec<Value>>();
+        let mut result = json!({ "provider": provider.name(), "supported_codes": codes });
+        if !search.is_empty() {
+            result["search"] = json!(search);
+        }
+        print_json(&result);
+        return CommandStatus::Success;
+    }
+    if matching.is_empty() {
+        println!("No supported currency matches \"{}\".", search);
+    }
+    for (code, name) in matching {
+        println!("{}: {}", code, name);
+    }
+    CommandStatus::Success
+}
+
+// Warning printed in the interactive mode when the estimated usage crosses a threshold.
+pub fn quota_warning_message(estimate: &QuotaEstimate) -> String {
+    format!(
+        "Warning: about {}% of the request quota is used ({} of {} requests), it refreshes on {}.",
+        estimate.percent_used(), estimate.requests_used, estimate.plan_quota, estimate.refresh_date
+    )
+}
+
+async fn quota(args: &[&str], options: &Options) -> CommandStatus {
+    match args {
+        [] => (),
+        ["warn", "set", thresholds] => {
+            return match parse_thresholds(thresholds) {
+                Ok(parsed) => {
+                    let thresholds = parsed.iter().map(u32::to_string).collect::<Vec<String>>().join(",");
+                    match set_quota_warning_thresholds(Some(thresholds.clone())) {
+                        Ok(_) => report_success(options, &format!("Warning at {}% of the request quota.", thresholds.replace(',', "%, "))),
+                        Err(e) => report_error(options, "Error setting quota warnings", e, CommandStatus::Failure),
                     }
                 }
-                Err(e) => {
-                    println!("Error getting exchange rates: {}", e);
-                }
+                Err(e) => report_error(options, "Error setting quota warnings", e, CommandStatus::UsageError),
+            };
+        }
+        ["warn", "remove"] => {
+            return match set_quota_warning_thresholds(None) {
+                Ok(_) => report_success(options, "Quota warnings set back to the default thresholds."),
+                Err(e) => report_error(options, "Error removing quota warnings", e, CommandStatus::Failure),
+            };
+        }
+        _ => return usage_error(options, "Please provide no arguments to show the quota, or warn set/remove to change the warnings.", Some("quota warn set 80,95")),
+    }
+    let provider = match provider_from_config() {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error getting quota", e, CommandStatus::Failure),
+    };
+    let path = match usage_path() {
+        Ok(path) => path,
+        Err(e) => return report_error(options, "Error getting quota", e, CommandStatus::Failure),
+    };
+    let has_quota = provider.name() == QUOTA_PROVIDER;
+    // Offline, the usage is estimated from the last check.
+    if has_quota && !options.offline {
+        if options.text() {
+            println!("Getting the quota of {}...", provider.name());
+        }
+        if let Err(e) = refresh_quota(&path).await {
+            return report_error(options, "Error getting quota", e, CommandStatus::Failure);
+        }
+    }
+    let usage = match read_usage(&path) {
+        Ok(usage) => usage,
+        Err(e) => return report_error(options, "Error getting quota", e, CommandStatus::Failure),
+    };
+    let today = today();
+    let requests_today = usage.requests_on(provider.name(), today);
+    let requests_last_30_days = usage.requests_in_days(provider.name(), today, 30);
+    let estimate = usage.estimate(provider.name(), today);
+    if options.json() {
+        let mut result = json!({
+            "provider": provider.name(),
+            "requests_today": requests_today,
+            "requests_last_30_days": requests_last_30_days,
+            "warning_thresholds": warning_thresholds(),
+        });
+        if let (Some(estimate), Some(quota)) = (&estimate, usage.quotas.get(provider.name())) {
+            result["plan_quota"] = json!(estimate.plan_quota);
+            result["requests_used"] = json!(estimate.requests_used);
+            result["requests_remaining"] = json!(estimate.plan_quota.saturating_sub(estimate.requests_used));
+            result["refresh_day_of_month"] = json!(quota.refresh_day_of_month);
+            result["refresh_date"] = json!(estimate.refresh_date.to_string());
+        }
+        print_json(&result);
+        return CommandStatus::Success;
+    }
+    match (has_quota, &estimate, usage.quotas.get(provider.name())) {
+        (false, _, _) => println!("{} has no request quota.", provider.name()),
+        (true, Some(estimate), Some(quota)) => {
+            if options.offline {
+                println!("Offline mode: estimated from the quota checked {} ago and the {} requests made since.", format_age(unix_now() - quota.time_fetched_unix), quota.requests_since);
             }
+            println!("Requests used: {} of {} ({}%)", estimate.requests_used, estimate.plan_quota, estimate.percent_used());
+            println!("Requests remaining: {}", estimate.plan_quota.saturating_sub(estimate.requests_used));
+            println!("The quota refreshes on day {} of the month, next on {}.", quota.refresh_day_of_month, estimate.refresh_date);
         }
-        Some(Command::GetRate) => {
-            if args.len() != 2 {
-                println!("Please provide two currencies to get the exchange rate between.");
-                println!("[Example: rate USD EUR]");
-            } else {
-                println!("Getting the exchange rate between {} and {}...", args[0], args[1]);
-                let exchange_rate_response = get_exchange_rate(args[0], args[1]).await;
-                match exchange_rate_response {
-                    Ok(response) => {
-                        println!("Exchange rate from {} to {}: {}", response.base_code, response.target_code, response.conversion_rate);
-                    }
-                    Err(e) => {
-                        println!("Error getting exchange rate: {}", e);
-                    }
+        (true, _, _) => println!("The quota hasn't been checked since it last refreshed. Run quota without --offline first."),
+    }
+    println!("Requests made by this program today: {}, in the last 30 days: {}", requests_today, requests_last_30_days);
+    CommandStatus::Success
+}
+
+// Input that isn't a command can still be a conversion, e.g. 100 usd to eur or usd eur.
+async fn phrase(command: &str, args: &[&str], options: &Options) -> CommandStatus {
+    let input = std::iter::once(command).chain(args.iter().copied()).collect::<Vec<&str>>().join(" ");
+    match parse_phrase(&input) {
+        Some(Phrase::Convert { amount, from, to }) => convert(&[from, to, &amount.to_string()], options).await,
+        Some(Phrase::Rate { from, to }) => get_rate(&[from, to], options).await,
+        None => usage_error(options, "Command not recognized. Type help for a list of commands.", None),
+    }
+}
+
+fn help() -> CommandStatus {
+    println!("==== Help ====");
+    println!("Available commands:");
+    println!("help - Get a list of commands");
+    println!("all [BASE_CURRENCY] - Get all exchange rates for base currency (default is USD)");
+    println!("rate [CURRENCY_1] [CURRENCY_2] [--details] - Get the exchange rate between two currencies, with --details also the name, symbol, locale and flag of the second one");
+    println!("convert [CURRENCY_FROM] [CURRENCY_TO] [AMOUNT] [--round half-even/half-up/truncate] - Convert an amount from one currency to another (rounding defaults to half-even)");
+    println!("convert [CURRENCY_FROM] [AMOUNT] [CURRENCY_1,CURRENCY_2,...] or [--all] - Convert an amount to many currencies at once");
+    println!("batch [INPUT_FILE] [TARGET_CURRENCY] [--output FILE] - Convert every row of a CSV file with amount, currency and optional date columns to the target currency");
+    println!("calc [EXPRESSION] in [CURRENCY] - Add up amounts in different currencies, e.g. 100 USD + 250 EUR - 3000 JPY in GBP (calc can be left out)");
+    println!("codes [SEARCH] - List the currencies supported by the provider, or only the ones matching the search, e.g. codes franc");
+    println!("history [BASE_CURRENCY] [DATE] [TARGET_CURRENCY] - Get all exchange rates for base currency on a past date (YYYY-MM-DD), or only the rate to the target currency");
+    println!("watch [CURRENCY_1] [CURRENCY_2] [--interval SECONDS] - Follow the exchange rate between two currencies and show every change until Ctrl-C is pressed (checked every 60 seconds by default)");
+    println!("alert [add/list/remove] [PAIR] [above/below/change] [VALUE] - Manage alerts on exchange rates, e.g. alert add EURUSD above 1.12 or alert add USDJPY change 2%");
+    println!("alert [command/webhook] [set/remove] [VALUE] - Run a command or call a webhook when an alert is triggered");
+    println!("store [snapshots/rate] [CURRENCY_1] [CURRENCY_2] [FROM_DATE] [TO_DATE] - List the stored tables of exchange rates, or the stored rates between two currencies in a date range");
+    println!("Conversions can also be typed without a command, e.g. 100 usd to eur, €50 in $, 50eur->chf or usd eur");
+    println!("Add --date YYYY-MM-DD to all, rate or convert to use the exchange rates of a past date");
+    println!("Add --offline to all, rate or convert to use the last cached rates without connecting to the internet");
+    println!("Add --format json to any command to get the result as a JSON object");
+    println!("Add --format csv or --format tsv [--output FILE] to all or history to export the rate table");
+    println!("info [CURRENCY] - Show the ISO 4217 details of a currency");
+    println!("key [view/set/remove] [API_KEY] - View, set, or remove the API key");
+    println!("provider [view/set/codes] [PROVIDER] - View or set the exchange rate provider, or list its supported currencies");
+    println!("quota [warn set/remove] [THRESHOLDS] - Show the requests used and remaining this month, or set the percentages of the quota to warn about");
+    println!("exit - Exit the program");
+    println!("==============");
+    CommandStatus::Success
+}
+
+async fn get_all_rates(args: &[&str], options: &Options) -> CommandStatus {
+    let base_currency = if args.is_empty() {
+        if options.text() {
+            println!("Base currency not provided. Using USD as the base currency.");
+        }
+        "USD"
+    } else {
+        args[0]
+    };
+    if let Err(e) = validate_currency(base_currency) {
+        return report_error(options, "Error getting exchange rates", e, CommandStatus::UsageError);
+    }
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error getting exchange rates", e, CommandStatus::Failure),
+    };
+    if let Err(e) = provider.check_supported(&[base_currency]).await {
+        return report_error(options, "Error getting exchange rates", e, CommandStatus::UsageError);
+    }
+    if options.text() {
+        println!("Getting all exchange rates for {}...", base_currency);
+    }
+    let exchange_rate_response = match options.date {
+        Some(date) => provider.history(base_currency, date).await,
+        None => provider.latest(base_currency).await,
+    };
+    match exchange_rate_response {
+        Ok(response) => {
+            if let Some(delimiter) = options.format.delimiter() {
+                return export_rate_table(&response, delimiter, options);
+            }
+            if options.json() {
+                let mut table = json!({
+                    "base_code": response.base_code,
+                    "time_last_update_unix": response.time_last_update_unix,
+                    "time_last_update_utc": response.time_last_update_utc,
+                    "time_next_update_unix": response.time_next_update_unix,
+                    "time_next_update_utc": response.time_next_update_utc,
+                    "conversion_rates": response.conversion_rates,
+                });
+                if let Some(date) = options.date {
+                    table["date"] = json!(date.to_string());
                 }
+                print_json(&table);
+                return CommandStatus::Success;
+            }
+            print_rates_time(options, response.time_last_update_unix, &response.time_last_update_utc);
+            match options.date {
+                Some(date) => println!("Exchange rates for {} on {}:", response.base_code, date),
+                None => println!("Exchange rates for {}:", response.base_code),
             }
+            for (currency, rate) in response.conversion_rates.iter() {
+                println!("{}: {}", currency, rate);
+            }
+            CommandStatus::Success
+        }
+        Err(e) => report_error(options, "Error getting exchange rates", e, CommandStatus::Failure),
+    }
+}
+
+fn export_rate_table(table: &ApiMultirateResponse, delimiter: u8, options: &Options) -> CommandStatus {
+    let result = match &options.output_file {
+        Some(path) => match File::create(path) {
+            Ok(file) => write_rate_table(table, delimiter, file),
+            Err(e) => Err(e.into()),
+        },
+        None => write_rate_table(table, delimiter, stdout()),
+    };
+    match (result, &options.output_file) {
+        (Ok(_), Some(path)) => {
+            println!("Exchange rates for {} written to {}.", table.base_code, path);
+            CommandStatus::Success
+        }
+        (Ok(_), None) => CommandStatus::Success,
+        (Err(e), _) => report_error(options, "Error exporting exchange rates", e, CommandStatus::Failure),
+    }
+}
+
+// Details of the target currency for rate --details, from the provider when it has them, otherwise from the built-in registry.
+async fn target_details(provider: &CachedProvider, from: &str, to: &str) -> Value {
+    match provider.enriched(from, to).await {
+        Ok(enriched) => {
+            let target_data = enriched.target_data;
+            json!({
+                "source": provider.name(),
+                "currency_name": target_data.currency_name,
+                "currency_name_short": target_data.currency_name_short,
+                "symbol": target_data.symbol(),
+                "locale": target_data.locale,
+                "two_letter_code": target_data.two_letter_code,
+                "flag_url": target_data.flag_url,
+            })
+        }
+        Err(e) => {
+            let currency = find_currency(to);
+            json!({
+                "source": "built-in",
+                "currency_name": currency.map(|currency| currency.name),
+                "symbol": currency.map(|currency| currency.symbol),
+                "unavailable_reason": e.to_string(),
+            })
         }
-        Some(Command::Convert) => {
-            if args.len() < 3 {
-                println!("Please provide a currency to convert from, a currency to convert to, and an amount to convert.");
-                println!("[Example: convert USD EUR 100]");
+    }
+}
+
+fn print_target_details(details: &Value) {
+    let text = |key: &str| details[key].as_str().unwrap_or("unknown").to_string();
+    if details["source"] == "built-in" {
+        println!("Currency details from the provider are not available ({}), showing the built-in ones.", text("unavailable_reason").trim_end_matches('.'));
+        println!("Currency: {}", text("currency_name"));
+        println!("Symbol: {}", text("symbol"));
+        return;
+    }
+    println!("Currency: {} ({})", text("currency_name"), text("currency_name_short"));
+    println!("Symbol: {}", text("symbol"));
+    println!("Locale: {} ({})", text("locale"), text("two_letter_code"));
+    println!("Flag: {}", text("flag_url"));
+}
+
+async fn get_rate(args: &[&str], options: &Options) -> CommandStatus {
+    // rate USD EUR --details also shows the target currency's names, symbol, locale and flag.
+    let details = args.contains(&"--details");
+    let args = args.iter().copied().filter(|arg| *arg != "--details").collect::<Vec<&str>>();
+    if args.len() != 2 {
+        return usage_error(options, "Please provide two currencies to get the exchange rate between.", Some("rate USD EUR"));
+    }
+    if let Err(e) = validate_currency(args[0]).and(validate_currency(args[1])) {
+        return report_error(options, "Error getting exchange rate", e, CommandStatus::UsageError);
+    }
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error getting exchange rate", e, CommandStatus::Failure),
+    };
+    if let Err(e) = provider.check_supported(&[args[0], args[1]]).await {
+        return report_error(options, "Error getting exchange rate", e, CommandStatus::UsageError);
+    }
+    if options.text() {
+        println!("Getting the exchange rate between {} and {}...", args[0], args[1]);
+    }
+    let exchange_rate = rate_on_date(&provider, args[0], args[1], options).await;
+    match exchange_rate {
+        Ok(rate) => {
+            let target_details = if details {
+                Some(target_details(&provider, &rate.from, &rate.to).await)
             } else {
-                let from_currency = args[0];
-                let to_currency = args[1];
-                let amount = match args[2].parse::<f64>() {
-                    Ok(amount) => amount,
-                    Err(_) => {
-                        println!("Invalid amount provided. Please provide a valid number.");
-                        return;
-                    }
-                };
-                println!("Converting {} {} to {}...", amount, from_currency, to_currency);
-                let conversion_response = convert(from_currency, to_currency, amount).await;
-                match conversion_response {
-                    Ok(response) => {
-                        println!("{} {} is equal to {} {}.", amount, from_currency, response.conversion_result, to_currency);
-                        println!("Exchange rate used: {}", response.conversion_rate);
-                    }
-                    Err(e) => {
-                        println!("Error converting currency: {}", e);
-                    }
+                None
+            };
+            if options.json() {
+                let mut rate_json = rate_json(&rate, options.date);
+                if let Some(target_details) = target_details {
+                    rate_json["target_details"] = target_details;
                 }
+                print_json(&rate_json);
+                return CommandStatus::Success;
+            }
+            print_rates_time(options, rate.time_last_update_unix, &rate.time_last_update_utc);
+            match options.date {
+                Some(date) => println!("Exchange rate from {} to {} on {}: {}", rate.from, rate.to, date, rate.display_rate()),
+                None => println!("Exchange rate from {} to {}: {}", rate.from, rate.to, rate.display_rate()),
+            }
+            if rate.is_triangulated() {
+                println!("Derived through: {}", rate.path_description());
+            }
+            if let Some(target_details) = target_details {
+                print_target_details(&target_details);
+            }
+            CommandStatus::Success
+        }
+        Err(e) => report_error(options, "Error getting exchange rate", e, CommandStatus::Failure),
+    }
+}
 
+async fn convert(args: &[&str], options: &Options) -> CommandStatus {
+    // convert USD 100 EUR,GBP,JPY or convert USD 100 --all
+    let all_currencies = args.contains(&"--all");
+    let args = args.iter().copied().filter(|arg| *arg != "--all").collect::<Vec<&str>>();
+    if all_currencies || (args.len() == 3 && Decimal::from_str(args[1]).is_ok()) {
+        return convert_to_many(&args, all_currencies, options).await;
+    }
+    if args.len() < 3 {
+        return usage_error(options, "Please provide a currency to convert from, a currency to convert to, and an amount to convert.", Some("convert USD EUR 100"));
+    }
+    let from_currency = args[0];
+    let to_currency = args[1];
+    let amount = match Decimal::from_str(args[2]) {
+        Ok(amount) => amount,
+        Err(_) => return usage_error(options, "Invalid amount provided. Please provide a valid number.", None),
+    };
+    let target_currency = match validate_currency(from_currency).and(validate_currency(to_currency)) {
+        Ok(currency) => currency,
+        Err(e) => return report_error(options, "Error converting currency", e, CommandStatus::UsageError),
+    };
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error converting currency", e, CommandStatus::Failure),
+    };
+    if let Err(e) = provider.check_supported(&[from_currency, to_currency]).await {
+        return report_error(options, "Error converting currency", e, CommandStatus::UsageError);
+    }
+    if options.text() {
+        println!("Converting {} {} to {}...", amount, from_currency, to_currency);
+    }
+    let exchange_rate = rate_on_date(&provider, from_currency, to_currency, options).await;
+    match exchange_rate {
+        Ok(rate) => {
+            let result = match rate.convert(amount) {
+                Ok(result) => options.rounding_mode.round(result, target_currency.rounding_decimals()),
+                Err(e) => return report_error(options, "Error converting currency", e, CommandStatus::Failure),
+            };
+            if options.json() {
+                let mut conversion = rate_json(&rate, options.date);
+                conversion["amount"] = decimal_json(amount);
+                conversion["result"] = decimal_json(result);
+                conversion["rounding_mode"] = json!(options.rounding_mode.name());
+                print_json(&conversion);
+                return CommandStatus::Success;
             }
+            print_rates_time(options, rate.time_last_update_unix, &rate.time_last_update_utc);
+            println!("{} {} is equal to {} {}.", amount, rate.from, result, rate.to);
+            println!("Exchange rate used: {}", rate.display_rate());
+            if rate.is_triangulated() {
+                println!("Derived through: {}", rate.path_description());
+            }
+            CommandStatus::Success
         }
+        Err(e) => report_error(options, "Error converting currency", e, CommandStatus::Failure),
+    }
+}
 
-        Some(Command::Key) => {
-            if args.is_empty() {
-                println!("Please provide a command to view, set, or remove the API key.");
-                println!("[Example: key view]");
+async fn batch(args: &[&str], options: &Options) -> CommandStatus {
+    if args.len() != 2 {
+        return usage_error(options, "Please provide a CSV file of amounts and a currency to convert them to.", Some("batch expenses.csv EUR --output expenses-eur.csv"));
+    }
+    if options.json() && options.output_file.is_none() {
+        return usage_error(options, "--format json can only be used with --output, the converted rows are written to the file.", Some("batch expenses.csv EUR --output expenses-eur.csv --format json"));
+    }
+    if let Err(e) = validate_currency(args[1]) {
+        return report_error(options, "Error converting the file", e, CommandStatus::UsageError);
+    }
+    let input = match File::open(args[0]) {
+        Ok(input) => input,
+        Err(e) => return report_error(options, "Error opening the input file", e.into(), CommandStatus::Failure),
+    };
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error converting the file", e, CommandStatus::Failure),
+    };
+    if let Err(e) = provider.check_supported(&[args[1]]).await {
+        return report_error(options, "Error converting the file", e, CommandStatus::UsageError);
+    }
+    let delimiter = options.format.delimiter().unwrap_or(b',');
+    let result = match &options.output_file {
+        Some(path) => match File::create(path) {
+            Ok(output) => convert_batch(&provider, input, output, delimiter, args[1], options.date, options.rounding_mode).await,
+            Err(e) => Err(e.into()),
+        },
+        None => convert_batch(&provider, input, stdout(), delimiter, args[1], options.date, options.rounding_mode).await,
+    };
+    let summary = match result {
+        Ok(summary) => summary,
+        Err(e) => return report_error(options, "Error converting the file", e, CommandStatus::Failure),
+    };
+    let status = if summary.failed == 0 { CommandStatus::Success } else { CommandStatus::Failure };
+    // Without --output the summary would end up in the converted table.
+    if let Some(path) = &options.output_file {
+        if options.json() {
+            print_json(&json!({ "converted": summary.converted, "failed": summary.failed, "output": path }));
+        } else {
+            println!("Converted {} rows to {}, written to {}.", summary.converted, args[1].to_uppercase(), path);
+            if summary.failed > 0 {
+                println!("{} rows couldn't be converted, see the error column.", summary.failed);
+            }
+        }
+    }
+    status
+}
+
+async fn calc(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide an expression with amounts and the currency of the result.", Some("calc 100 USD + 250 EUR - 3000 JPY in GBP"));
+    }
+    let input = args.join(" ");
+    let expression = match parse_expression(&input) {
+        Ok(expression) => expression,
+        Err(e) => return report_error(options, "Error calculating", e, CommandStatus::UsageError),
+    };
+    let terms = expression.terms();
+    let target_currency = match terms.iter().try_for_each(|term| validate_currency(&term.currency).map(|_| ())).and(validate_currency(&expression.target)) {
+        Ok(currency) => currency,
+        Err(e) => return report_error(options, "Error calculating", e, CommandStatus::UsageError),
+    };
+    // Mixing numbers and amounts is an error with any rates, so it's found before anything is requested.
+    let placeholder_rates = terms.iter().map(|term| (term.currency.clone(), Decimal::ONE)).collect::<HashMap<String, Decimal>>();
+    if let Err(e) = expression.evaluate(&placeholder_rates) {
+        return report_error(options, "Error calculating", e, CommandStatus::UsageError);
+    }
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error calculating", e, CommandStatus::Failure),
+    };
+    let codes = terms.iter().map(|term| term.currency.as_str()).chain([expression.target.as_str()]).collect::<Vec<&str>>();
+    if let Err(e) = provider.check_supported(&codes).await {
+        return report_error(options, "Error calculating", e, CommandStatus::UsageError);
+    }
+    // One rate per currency, the terms in the same currency share it.
+    let mut rates = HashMap::new();
+    let mut derived_rates = HashMap::new();
+    for term in &terms {
+        if rates.contains_key(&term.currency) {
+            continue;
+        }
+        match rate_on_date(&provider, &term.currency, &expression.target, options).await {
+            Ok(rate) => {
+                rates.insert(term.currency.clone(), rate.rate);
+                derived_rates.insert(term.currency.clone(), rate);
+            }
+            Err(e) => return report_error(options, "Error calculating", e, CommandStatus::Failure),
+        }
+    }
+    let result = match expression.evaluate(&rates) {
+        Ok(result) => options.rounding_mode.round(result, target_currency.rounding_decimals()),
+        Err(e) => return report_error(options, "Error calculating", e, CommandStatus::Failure),
+    };
+    let breakdown = terms.iter().filter_map(|term| {
+        let rate = derived_rates.get(&term.currency)?;
+        let converted = options.rounding_mode.round(rate.convert(term.amount).ok()?, target_currency.rounding_decimals());
+        Some((term, rate, converted))
+    });
+    if options.json() {
+        let terms = breakdown.map(|(term, rate, converted)| {
+            let mut term_json = rate_json(rate, options.date);
+            term_json["amount"] = decimal_json(term.amount);
+            term_json["result"] = decimal_json(converted);
+            term_json
+        }).collect::<Vec<Value>>();
+        let mut calculation = json!({
+            "expression": input,
+            "to": expression.target,
+            "terms": terms,
+            "result": decimal_json(result),
+            "rounding_mode": options.rounding_mode.name(),
+        });
+        if let Some(date) = options.date {
+            calculation["date"] = json!(date.to_string());
+        }
+        print_json(&calculation);
+        return CommandStatus::Success;
+    }
+    if let Some(date) = options.date {
+        println!("Rates for {}.", date);
+    }
+    let rows = breakdown.map(|(term, rate, converted)| {
+        [format!("{} {}", term.amount, term.currency), format!("{} {}", converted, expression.target), rate.display_rate().to_string()]
+    }).collect::<Vec<[String; 3]>>();
+    for line in format_table(&["Amount", "Converted", "Rate"], &rows) {
+        println!("{}", line);
+    }
+    println!("Result: {} {}", result, expression.target);
+    CommandStatus::Success
+}
+
+// Shortcut for all --date or rate --date.
+async fn history(args: &[&str], options: &Options) -> CommandStatus {
+    if args.len() < 2 || args.len() > 3 {
+        return usage_error(options, "Please provide a base currency, a date and optionally a target currency.", Some("history USD 2024-03-14 EUR"));
+    }
+    let date = match parse_date(args[1]) {
+        Some(date) => date,
+        None => return usage_error(options, INVALID_DATE, None),
+    };
+    let options = Options { date: Some(date), ..options.clone() };
+    if args.len() == 3 {
+        get_rate(&[args[0], args[2]], &options).await
+    } else {
+        get_all_rates(&[args[0]], &options).await
+    }
+}
+
+async fn watch(args: &[&str], options: &Options) -> CommandStatus {
+    if args.len() != 2 {
+        return usage_error(options, "Please provide two currencies to watch the exchange rate between.", Some("watch USD EUR --interval 5m"));
+    }
+    if let Err(e) = validate_currency(args[0]).and(validate_currency(args[1])) {
+        return report_error(options, "Error watching exchange rate", e, CommandStatus::UsageError);
+    }
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error watching exchange rate", e, CommandStatus::Failure),
+    };
+    if let Err(e) = provider.check_supported(&[args[0], args[1]]).await {
+        return report_error(options, "Error watching exchange rate", e, CommandStatus::UsageError);
+    }
+    let (from, to) = (args[0].to_uppercase(), args[1].to_uppercase());
+    if options.text() {
+        println!("Watching the exchange rate from {} to {} every {} seconds. Press Ctrl-C to stop.", from, to, options.interval.as_secs());
+    }
+    let result = watch_rate(&provider, &from, &to, options.interval, |change| match change {
+        Ok(change) => print_rate_change(options, &change),
+        Err(e) => {
+            report_error(options, "Error getting exchange rate", e, CommandStatus::Failure);
+        }
+    }).await;
+    match result {
+        Ok(_) => {
+            if options.text() {
+                println!("Stopped watching the exchange rate from {} to {}.", from, to);
+            }
+            CommandStatus::Success
+        }
+        Err(e) => report_error(options, "Error watching exchange rate", e, CommandStatus::Failure),
+    }
+}
+
+fn print_rate_change(options: &Options, change: &RateChange) {
+    let rate = &change.rate;
+    let change_display = change.change.round_dp(RATE_DISPLAY_DECIMALS).normalize();
+    let percent_display = change.change_percent.round_dp(4).normalize();
+    if options.json() {
+        let mut rate_change = rate_json(rate, None);
+        rate_change["change"] = decimal_json(change_display);
+        rate_change["change_percent"] = decimal_json(percent_display);
+        print_json(&rate_change);
+    } else if change.change.is_zero() {
+        println!("{}: 1 {} = {} {}", rate.time_last_update_utc, rate.from, rate.display_rate(), rate.to);
+    } else {
+        let sign = if change.change.is_sign_positive() { "+" } else { "" };
+        println!("{}: 1 {} = {} {} ({}{}, {}{}% since start)", rate.time_last_update_utc, rate.from, rate.display_rate(), rate.to, sign, change_display, sign, percent_display);
+    }
+}
+
+async fn alert(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide a command to add, list or remove alerts.", Some("alert add EURUSD above 1.12"));
+    }
+    let path = match alerts_path() {
+        Ok(path) => path,
+        Err(e) => return report_error(options, "Error reading alerts", e, CommandStatus::Failure),
+    };
+    match args[0] {
+        "add" => {
+            if args.len() != 4 {
+                return usage_error(options, "Please provide a currency pair, a condition and a value.", Some("alert add USDJPY change 2%"));
+            }
+            let (from, to) = match parse_pair(args[1]) {
+                Ok(pair) => pair,
+                Err(e) => return report_error(options, "Error adding alert", e, CommandStatus::UsageError),
+            };
+            if let Err(e) = validate_currency(&from).and(validate_currency(&to)) {
+                return report_error(options, "Error adding alert", e, CommandStatus::UsageError);
+            }
+            let condition = match parse_condition(args[2], args[3]) {
+                Ok(condition) => condition,
+                Err(e) => return report_error(options, "Error adding alert", e, CommandStatus::UsageError),
+            };
+            // Changes are measured from the current rate, when it can't be fetched the first checked rate is used.
+            let reference_rate = match cached_provider_from_config(options.offline) {
+                Ok(provider) => provider.derived_rate(&from, &to).await.ok().map(|rate| rate.rate),
+                Err(_) => None,
+            };
+            match add_alert(&path, &from, &to, condition, reference_rate) {
+                Ok(alert) => report_success(options, &format!("Alert {} added: {}/{} {}.", alert.id, alert.from, alert.to, alert.condition)),
+                Err(e) => report_error(options, "Error adding alert", e, CommandStatus::Failure),
+            }
+        }
+        "list" => {
+            let alerts = match read_alerts(&path) {
+                Ok(alerts) => alerts,
+                Err(e) => return report_error(options, "Error reading alerts", e, CommandStatus::Failure),
+            };
+            if options.json() {
+                let alerts = alerts.iter().map(|alert| json!({
+                    "id": alert.id,
+                    "from": alert.from,
+                    "to": alert.to,
+                    "condition": alert.condition.to_string(),
+                    "reference_rate": alert.reference_rate.map(decimal_json),
+                })).collect::<Vec<Value>>();
+                print_json(&json!({ "alerts": alerts }));
+                return CommandStatus::Success;
+            }
+            if alerts.is_empty() {
+                println!("No alerts set.");
+            }
+            for alert in alerts {
+                println!("{}: {}/{} {}", alert.id, alert.from, alert.to, alert.condition);
+            }
+            CommandStatus::Success
+        }
+        "remove" => {
+            let id = match args.get(1).map(|id| id.parse::<u32>()) {
+                Some(Ok(id)) => id,
+                _ => return usage_error(options, "Please provide the id of the alert to remove.", Some("alert remove 1")),
+            };
+            match remove_alert(&path, id) {
+                Ok(_) => report_success(options, &format!("Alert {} removed.", id)),
+                Err(e) => report_error(options, "Error removing alert", e, CommandStatus::Failure),
+            }
+        }
+        "command" | "webhook" => {
+            let value = match args.get(1) {
+                Some(&"set") if args.len() > 2 => Some(args[2..].join(" ")),
+                Some(&"remove") => None,
+                _ => return usage_error(options, "Please provide a command or a webhook URL to set, or remove it.", Some("alert webhook set http://localhost:8080/alerts")),
+            };
+            let (result, name) = if args[0] == "command" {
+                (set_alert_command(value.clone()), "Alert command")
             } else {
-                match args[0] {
-                    "view" => {
-                        match read_config() {
-                            Ok(config) => {
-                                println!("API key: {}", config.api_key);
-                            }
-                            Err(e) => {
-                                println!("Error reading API key: {}", e);
-                            }
-                        }
-                    }
-                    "set" => {
-                        if args.len() < 2 {
-                            println!("Please provide an API key to set.");
-                            println!("[Example: key set YOUR_API_KEY]");
-                        } else {
-                            let api_key = args[1];
-                            match write_config(api_key.to_string()) {
-                                Ok(_) => {
-                                    println!("API key set.");
-                                }
-                                Err(e) => {
-                                    println!("Error setting API key: {}", e);
-                                }
-                            }
-                        }
-                    }
-                    "remove" => {
-                        match remove_config() {
-                            Ok(_) => {
-                                println!("API key removed.");
-                            }
-                            Err(e) => {
-                                println!("Error removing API key: {}", e);
-                            }
-                        }
+                (set_alert_webhook(value.clone()), "Alert webhook")
+            };
+            match (result, value) {
+                (Ok(_), Some(_)) => report_success(options, &format!("{} set.", name)),
+                (Ok(_), None) => report_success(options, &format!("{} removed.", name)),
+                (Err(e), _) => report_error(options, "Error setting alert notification", e, CommandStatus::Failure),
+            }
+        }
+        _ => usage_error(options, "Command not recognized. Please provide a command to add, list or remove alerts.", Some("alert list")),
+    }
+}
+
+fn store(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide a command to list the stored snapshots or the stored rates of a pair.", Some("store rate USD EUR 2024-03-01 2024-03-31"));
+    }
+    match args[0] {
+        "snapshots" => {
+            let snapshots = match RateStore::from_config().and_then(|store| store.snapshots()) {
+                Ok(snapshots) => snapshots,
+                Err(e) => return report_error(options, "Error reading stored exchange rates", e, CommandStatus::Failure),
+            };
+            if options.json() {
+                let snapshots = snapshots.iter().map(|snapshot| json!({
+                    "provider": snapshot.provider,
+                    "base_code": snapshot.base_code,
+                    "time_last_update_unix": snapshot.time_last_update_unix,
+                    "time_last_update_utc": snapshot.time_last_update_utc,
+                    "rate_count": snapshot.rate_count,
+                })).collect::<Vec<Value>>();
+                print_json(&json!({ "snapshots": snapshots }));
+                return CommandStatus::Success;
+            }
+            if snapshots.is_empty() {
+                println!("No exchange rates stored yet.");
+            }
+            for snapshot in snapshots {
+                println!("{} - {} {} ({} rates)", snapshot.time_last_update_utc, snapshot.provider, snapshot.base_code, snapshot.rate_count);
+            }
+            CommandStatus::Success
+        }
+        "rate" => {
+            if args.len() < 3 || args.len() > 5 {
+                return usage_error(options, "Please provide two currencies and optionally the first and last date.", Some("store rate USD EUR 2024-03-01 2024-03-31"));
+            }
+            if let Err(e) = validate_currency(args[1]).and(validate_currency(args[2])) {
+                return report_error(options, "Error reading stored exchange rates", e, CommandStatus::UsageError);
+            }
+            let dates = args[3..].iter().map(|date| parse_date(date)).collect::<Option<Vec<NaiveDate>>>();
+            let (start, end) = match dates.as_deref() {
+                Some([]) => (i64::MIN, i64::MAX),
+                Some([start]) => (start_of_day(*start), i64::MAX),
+                Some([start, end]) => (start_of_day(*start), start_of_day(*end) + 60 * 60 * 24 - 1),
+                _ => return usage_error(options, INVALID_DATE, None),
+            };
+            let rates = match RateStore::from_config().and_then(|store| store.pair_rates(args[1], args[2], start, end)) {
+                Ok(rates) => rates,
+                Err(e) => return report_error(options, "Error reading stored exchange rates", e, CommandStatus::Failure),
+            };
+            let (from, to) = (args[1].to_uppercase(), args[2].to_uppercase());
+            if options.json() {
+                let rates = rates.iter().map(|rate| json!({
+                    "time_last_update_unix": rate.time_last_update_unix,
+                    "time_last_update_utc": rate.time_last_update_utc,
+                    "rate": decimal_json(rate.rate.round_dp(RATE_DISPLAY_DECIMALS).normalize()),
+                })).collect::<Vec<Value>>();
+                print_json(&json!({ "from": from, "to": to, "rates": rates }));
+                return CommandStatus::Success;
+            }
+            if rates.is_empty() {
+                println!("No stored exchange rates from {} to {} in this period.", from, to);
+                return CommandStatus::Success;
+            }
+            println!("Stored exchange rates from {} to {}:", from, to);
+            for rate in rates {
+                println!("{}: {}", rate.time_last_update_utc, rate.rate.round_dp(RATE_DISPLAY_DECIMALS).normalize());
+            }
+            CommandStatus::Success
+        }
+        _ => usage_error(options, "Command not recognized. Please provide a command to list the stored snapshots or the stored rates of a pair.", Some("store snapshots")),
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp()
+}
+
+async fn convert_to_many(args: &[&str], all_currencies: bool, options: &Options) -> CommandStatus {
+    if args.len() != if all_currencies { 2 } else { 3 } {
+        return usage_error(options, "Please provide a currency to convert from, an amount, and a comma separated list of currencies or --all.", Some("convert USD 100 EUR,GBP,JPY"));
+    }
+    let from_currency = args[0].to_uppercase();
+    let amount = match Decimal::from_str(args[1]) {
+        Ok(amount) => amount,
+        Err(_) => return usage_error(options, "Invalid amount provided. Please provide a valid number.", None),
+    };
+    let mut targets = if all_currencies {
+        Vec::new()
+    } else {
+        args[2].split(',').filter(|code| !code.is_empty()).map(|code| code.to_uppercase()).collect::<Vec<String>>()
+    };
+    let valid = std::iter::once(&from_currency).chain(&targets).try_for_each(|code| validate_currency(code).map(|_| ()));
+    if let Err(e) = valid {
+        return report_error(options, "Error converting currency", e, CommandStatus::UsageError);
+    }
+    let provider = match cached_provider_from_config(options.offline) {
+        Ok(provider) => provider,
+        Err(e) => return report_error(options, "Error converting currency", e, CommandStatus::Failure),
+    };
+    let codes = std::iter::once(&from_currency).chain(&targets).map(String::as_str).collect::<Vec<&str>>();
+    if let Err(e) = provider.check_supported(&codes).await {
+        return report_error(options, "Error converting currency", e, CommandStatus::UsageError);
+    }
+    // The rates are derived from this one table, so the cache isn't searched again for every currency.
+    let table = match options.date {
+        Some(date) => provider.history(provider.reference_currency(), date).await,
+        None => provider.table_for_pair(&from_currency, &from_currency).await,
+    };
+    let table = match table {
+        Ok(table) => Some(table),
+        Err(e) if all_currencies => return report_error(options, "Error converting currency", e, CommandStatus::Failure),
+        // Listed currencies are still looked up one by one, each with its own error.
+        Err(_) => None,
+    };
+    if let (true, Some(table)) = (all_currencies, &table) {
+        let other_rates = table.conversion_rates.filter(|code, _| code != from_currency);
+        targets = other_rates.codes().map(String::from).collect();
+    }
+    if options.text() {
+        println!("Converting {} {} to {} currencies...", amount, from_currency, targets.len());
+    }
+    let mut status = CommandStatus::Success;
+    let mut rows = Vec::new();
+    let mut conversions = Vec::new();
+    for target in &targets {
+        let rate = match &table {
+            Some(table) if table_contains(table, &from_currency) && table_contains(table, target) => derive_rate(table, &from_currency, target),
+            _ => rate_on_date(&provider, &from_currency, target, options).await,
+        };
+        let conversion = match rate {
+            Ok(rate) => rate.convert(amount).map(|result| (rate, result)),
+            Err(e) => Err(e),
+        };
+        match conversion {
+            Ok((rate, result)) => {
+                let minor_units = find_currency(target).map(|currency| currency.rounding_decimals()).unwrap_or(2);
+                let result = options.rounding_mode.round(result, minor_units);
+                let mut conversion = rate_json(&rate, options.date);
+                conversion["result"] = decimal_json(result);
+                conversions.push(conversion);
+                rows.push([target.clone(), result.to_string(), rate.display_rate().to_string()]);
+            }
+            Err(e) => {
+                status = CommandStatus::Failure;
+                conversions.push(json!({ "to": target, "error": error_json(&e, false)["error"] }));
+                rows.push([target.clone(), e.to_string(), String::new()]);
+            }
+        }
+    }
+    if options.json() {
+        let mut result = json!({
+            "from": from_currency,
+            "amount": decimal_json(amount),
+            "rounding_mode": options.rounding_mode.name(),
+            "conversions": conversions,
+        });
+        if let Some(date) = options.date {
+            result["date"] = json!(date.to_string());
+        }
+        print_json(&result);
+        return status;
+    }
+    if let Some(date) = options.date {
+        println!("Rates for {}.", date);
+    }
+    for line in format_table(&["Currency", "Amount", "Rate"], &rows) {
+        println!("{}", line);
+    }
+    status
+}
+
+// Lines of a table with the columns aligned, the first one to the left and the rest to the right.
+fn format_table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> Vec<String> {
+    let mut widths = header.map(|title| title.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header = header.map(String::from);
+    std::iter::once(&header).chain(rows).map(|row| {
+        row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(column, (cell, width))| if column == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    }).collect()
+}
+
+fn info(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide a currency to show the details of.", Some("info EUR"));
+    }
+    match validate_currency(args[0]) {
+        Ok(currency) => {
+            if options.json() {
+                print_json(&json!({
+                    "code": currency.code,
+                    "name": currency.name,
+                    "numeric": currency.numeric.map(|numeric| format!("{:03}", numeric)),
+                    "symbol": currency.symbol,
+                    "minor_units": currency.minor_units,
+                    "countries": currency.countries,
+                }));
+                return CommandStatus::Success;
+            }
+            println!("==== {} ====", currency.code);
+            println!("Name: {}", currency.name);
+            println!("Numeric code: {}", currency.numeric_code());
+            println!("Symbol: {}", currency.symbol);
+            println!("Minor units: {}", currency.minor_units_description());
+            println!("Countries: {}", currency.countries.join(", "));
+            CommandStatus::Success
+        }
+        Err(e) => report_error(options, "Error getting currency details", e, CommandStatus::UsageError),
+    }
+}
+
+fn key(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide a command to view, set, or remove the API key.", Some("key view"));
+    }
+    match args[0] {
+        "view" => {
+            match read_api_key() {
+                Ok(api_key) => {
+                    if options.text() {
+                        println!("API key: {}", api_key);
+                    } else {
+                        print_json(&json!({ "api_key": api_key }));
                     }
-                    _ => {
-                        println!("Command not recognized. Please provide a command to view, set, or remove the API key.");
-                        println!("[Example: key view]");
+                    CommandStatus::Success
+                }
+                Err(e) => report_error(options, "Error reading API key", e, CommandStatus::Failure),
+            }
+        }
+        "set" => {
+            if args.len() < 2 {
+                return usage_error(options, "Please provide an API key to set.", Some("key set YOUR_API_KEY"));
+            }
+            let api_key = args[1];
+            match set_api_key(api_key.to_string()) {
+                Ok(_) => report_success(options, "API key set."),
+                Err(e) => report_error(options, "Error setting API key", e, CommandStatus::Failure),
+            }
+        }
+        "remove" => {
+            match remove_api_key() {
+                Ok(_) => report_success(options, "API key removed."),
+                Err(e) => report_error(options, "Error removing API key", e, CommandStatus::Failure),
+            }
+        }
+        _ => usage_error(options, "Command not recognized. Please provide a command to view, set, or remove the API key.", Some("key view")),
+    }
+}
+
+async fn provider(args: &[&str], options: &Options) -> CommandStatus {
+    if args.is_empty() {
+        return usage_error(options, "Please provide a command to view or set the provider, or list its currencies.", Some("provider view"));
+    }
+    match args[0] {
+        "view" => {
+            match provider_name_from_config() {
+                Ok(provider) => {
+                    if options.text() {
+                        println!("Provider: {}", provider);
+                        println!("Available providers: {}", PROVIDERS.join(", "));
+                    } else {
+                        print_json(&json!({ "provider": provider, "available_providers": PROVIDERS }));
                     }
+                    CommandStatus::Success
                 }
+                Err(e) => report_error(options, "Error reading provider", e, CommandStatus::Failure),
             }
         }
-        Some(Command::Exit) => {
-            println!("Exiting the program...");
-            exit(0);
+        "codes" => codes(&args[1..], options).await,
+        "set" => {
+            if args.len() < 2 {
+                let example = format!("provider set {}", DEFAULT_PROVIDER);
+                return usage_error(options, "Please provide a provider to set.", Some(&example));
+            }
+            let provider = args[1];
+            if let Err(e) = check_provider_name(provider) {
+                return report_error(options, "Error setting provider", e, CommandStatus::UsageError);
+            }
+            match set_provider(provider.to_string()) {
+                Ok(_) => report_success(options, &format!("Provider set to {}.", provider)),
+                Err(e) => report_error(options, "Error setting provider", e, CommandStatus::Failure),
+            }
         }
-        None => {
-            println!("Command not recognized. Type help for a list of commands.");
+        _ => usage_error(options, "Command not recognized. Please provide a command to view or set the provider, or list its currencies.", Some("provider view")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_table, match_command, new_command, rate_json, target_details, CommandStatus, COMMAND_NAMES};
+    use crate::cache::CachedProvider;
+    use crate::ecb::{Ecb, BASE_ECB_URL};
+    use crate::retry::RetryPolicy;
+    use crate::engine::derive_rate;
+    use crate::test_support::{temp_path, LATEST_USD};
+
+
+    #[tokio::test]
+    async fn test_exit_codes() {
+        assert_eq!(CommandStatus::Success.exit_code(), 0);
+        assert_eq!(CommandStatus::Failure.exit_code(), 1);
+        assert_eq!(CommandStatus::UsageError.exit_code(), 2);
+        assert_eq!(new_command("help", None).await, CommandStatus::Success);
+        assert_eq!(new_command("info", Some(vec!["EUR"])).await, CommandStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_usage_errors() {
+        assert_eq!(new_command("unknown", None).await, CommandStatus::UsageError);
+        assert_eq!(new_command("100", Some(vec!["usd", "to", "eur", "gbp"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("50eur->chf", Some(vec!["abc"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("rate", Some(vec!["USD"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("rate", Some(vec!["USD", "EUX"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "EUR", "abc"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "EUR", "100", "--round", "ceiling"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("rate", Some(vec!["USD", "EUR", "--format", "xml"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("key", Some(vec!["rotate"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("history", Some(vec!["USD", "14.03.2024"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "EUR", "100", "--date", "2024-02-30"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "100", "EUR,GBX"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "abc", "--all"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("convert", Some(vec!["USD", "100", "EUR", "--all"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("batch", Some(vec!["expenses.csv"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("calc", Some(vec!["100", "USD", "+", "5", "in", "GBP"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("calc", Some(vec!["100", "USX", "in", "GBP"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("batch", Some(vec!["expenses.csv", "EUX"])).await, CommandStatus::UsageError);
+        assert_eq!(new_command("batch", Some(vec!["expenses.csv", "EUR", "--format", "json"])).await, CommandStatus::UsageError);
+    }
+
+    #[test]
+    fn test_command_names() {
+        for name in COMMAND_NAMES {
+            assert!(match_command(name).is_some(), "{} is not a command", name);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_format_table() {
+        let rows = [
+            ["EUR".to_string(), "91.81".to_string(), "0.9181".to_string()],
+            ["JPY".to_string(), "14900".to_string(), "149".to_string()],
+        ];
+        let lines = format_table(&["Currency", "Amount", "Rate"], &rows);
+        assert_eq!(lines, vec![
+            "Currency  Amount    Rate",
+            "EUR        91.81  0.9181",
+            "JPY        14900     149",
+        ]);
+    }
+
+    #[test]
+    fn test_rate_json() {
+        let table = serde_json::from_str(LATEST_USD).unwrap();
+        let rate = rate_json(&derive_rate(&table, "EUR", "JPY").unwrap(), None);
+        assert_eq!(rate.to_string(), r#"{"from":"EUR","path":["EUR","USD","JPY"],"rate":162.2059688487,"time_last_update_unix":1710460801,"time_last_update_utc":"Fri, 15 Mar 2024 00:00:01 +0000","to":"JPY"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_target_details_fallback() {
+        let cache_dir = temp_path("details");
+        let provider = CachedProvider::new(Box::new(Ecb::new(BASE_ECB_URL, RetryPolicy::default()).unwrap()), cache_dir.clone(), false);
+        let details = target_details(&provider, "USD", "JPY").await;
+        assert_eq!(details["source"], "built-in");
+        assert_eq!(details["currency_name"], "Yen");
+        assert_eq!(details["symbol"], "¥");
+        assert_eq!(details["unavailable_reason"], "ecb has no currency details.");
+        let provider = CachedProvider::new(Box::new(Ecb::new(BASE_ECB_URL, RetryPolicy::default()).unwrap()), cache_dir, true);
+        let details = target_details(&provider, "USD", "JPY").await;
+        assert_eq!(details["unavailable_reason"], "Currency details are not available offline.");
+    }
+}
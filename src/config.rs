@@ -1,13 +1,37 @@
 use std::env;
 use directories::ProjectDirs;
 use std::fs;
+use std::path::Path;
 use serde::{Serialize, Deserialize};
 use anyhow::Result;
 use thiserror::Error;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Config {
-    pub api_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    // Run or called when an alert is triggered, see alerts.rs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert_webhook: Option<String>,
+    // Percentages of the request quota to warn about, e.g. "80,90,100", see usage.rs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota_warning_thresholds: Option<String>,
+    // Retries of failed requests to the API, see retry.rs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_max_attempts: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_base_delay_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_jitter: Option<f64>,
+    // HTTP status codes separated by commas, e.g. "500,502,503,504".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_statuses: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -18,35 +42,44 @@ enum ConfigError {
     ReadError,
     #[error("Error writing config.")]
     WriteError,
-    #[error("Error removing config.")]
-    RemoveError,
     #[error("JSON Parse Error.")]
     JsonParseError,
     #[error("Directory Error.")]
     DirectoryError,
 }
 
-pub fn get_config_dir() -> Result<String> {
+// One of the program's directories, e.g. the config directory, picked from the platform's project directories.
+fn project_dir(dir: fn(&ProjectDirs) -> &Path) -> Result<String> {
     let project_dirs = match ProjectDirs::from("", "", "CurrencyConverter") {
         Some(dirs) => dirs,
         None => return Err(ConfigError::DirectoryError.into()),
     };
-    let config_dir = match project_dirs.config_dir().to_str() {
-        Some(dir) => dir.to_string(),
-        None => return Err(ConfigError::DirectoryError.into()),
-    };
-    Ok(config_dir)
+    match dir(&project_dirs).to_str() {
+        Some(dir) => Ok(dir.to_string()),
+        None => Err(ConfigError::DirectoryError.into()),
+    }
+}
+
+pub fn get_config_dir() -> Result<String> {
+    project_dir(ProjectDirs::config_dir)
+}
+
+pub fn get_cache_dir() -> Result<String> {
+    project_dir(ProjectDirs::cache_dir)
+}
+
+pub fn get_data_dir() -> Result<String> {
+    project_dir(ProjectDirs::data_dir)
 }
 
-pub fn write_config(api_key: String) -> Result<()> {
-    let config = Config { api_key };
+pub fn write_config(config: &Config) -> Result<()> {
     let config_dir = get_config_dir()?;
     let config_file = format!("{}/config.json", config_dir);
-    let config_json = match serde_json::to_string(&config) {
+    let config_json = match serde_json::to_string(config) {
         Ok(json) => json,
         Err(_) => return Err(ConfigError::JsonParseError.into()),
     };
-    
+
     match fs::create_dir_all(config_dir) {
         Ok(_) => (),
         Err(_) => return Err(ConfigError::DirectoryError.into()),
@@ -54,40 +87,107 @@ pub fn write_config(api_key: String) -> Result<()> {
 
     match fs::write(config_file, config_json) {
         Ok(_) => Ok(()),
-        Err(_) => return Err(ConfigError::WriteError.into()),
+        Err(_) => Err(ConfigError::WriteError.into()),
     }
 }
 
-pub fn remove_config() -> Result<()> {
+// Reads only what is stored in the config file, without applying environment variables.
+// Used when updating a single setting, so values coming from the environment are not persisted.
+fn read_config_file() -> Result<Config> {
     let config_dir = get_config_dir()?;
     let config_file = format!("{}/config.json", config_dir);
-    if fs::metadata(&config_file).is_ok() {
-        match fs::remove_file(config_file) {
-            Ok(_) => Ok(()),
-            Err(_) => return Err(ConfigError::RemoveError.into()),
-        }
-    } else {
-        Err(ConfigError::NotFound.into())
+    if fs::metadata(&config_file).is_err() {
+        return Ok(Config::default());
+    }
+    let config_json = match fs::read_to_string(config_file) {
+        Ok(json) => json,
+        Err(_) => return Err(ConfigError::ReadError.into()),
+    };
+    match serde_json::from_str(&config_json) {
+        Ok(config) => Ok(config),
+        Err(_) => Err(ConfigError::JsonParseError.into()),
     }
 }
 
 pub fn read_config() -> Result<Config> {
-    let config_dir = get_config_dir()?;
-    let config_file = format!("{}/config.json", config_dir);
-    if fs::metadata(&config_file).is_ok() {
-        let config_json = match fs::read_to_string(config_file) {
-            Ok(json) => json,
-            Err(_) => return Err(ConfigError::ReadError.into()),
-        };
-        let config: Config = match serde_json::from_str(&config_json) {
-            Ok(config) => config,
-            Err(_) => return Err(ConfigError::JsonParseError.into()),
-        };
-        Ok(config)
-    } else {
-        match env::var("API_KEY") {
-            Ok(api_key) => return Ok(Config { api_key }),
-            Err(_) => return Err(ConfigError::NotFound.into()),
-        }
-    }
-}
\ No newline at end of file
+    let mut config = read_config_file()?;
+    if config.api_key.is_none() {
+        config.api_key = env::var("API_KEY").ok();
+    }
+    if config.provider.is_none() {
+        config.provider = env::var("PROVIDER").ok();
+    }
+    if config.base_url.is_none() {
+        config.base_url = env::var("API_BASE_URL").ok();
+    }
+    if config.alert_command.is_none() {
+        config.alert_command = env::var("ALERT_COMMAND").ok();
+    }
+    if config.alert_webhook.is_none() {
+        config.alert_webhook = env::var("ALERT_WEBHOOK").ok();
+    }
+    if config.quota_warning_thresholds.is_none() {
+        config.quota_warning_thresholds = env::var("QUOTA_WARNING_THRESHOLDS").ok();
+    }
+    if config.retry_max_attempts.is_none() {
+        config.retry_max_attempts = env::var("RETRY_MAX_ATTEMPTS").ok().and_then(|value| value.parse().ok());
+    }
+    if config.retry_base_delay_ms.is_none() {
+        config.retry_base_delay_ms = env::var("RETRY_BASE_DELAY_MS").ok().and_then(|value| value.parse().ok());
+    }
+    if config.retry_jitter.is_none() {
+        config.retry_jitter = env::var("RETRY_JITTER").ok().and_then(|value| value.parse().ok());
+    }
+    if config.retry_statuses.is_none() {
+        config.retry_statuses = env::var("RETRY_STATUSES").ok();
+    }
+    Ok(config)
+}
+
+pub fn read_api_key() -> Result<String> {
+    match read_config()?.api_key {
+        Some(api_key) => Ok(api_key),
+        None => Err(ConfigError::NotFound.into()),
+    }
+}
+
+pub fn set_api_key(api_key: String) -> Result<()> {
+    let mut config = read_config_file()?;
+    config.api_key = Some(api_key);
+    write_config(&config)
+}
+
+pub fn remove_api_key() -> Result<()> {
+    let mut config = read_config_file()?;
+    if config.api_key.take().is_none() {
+        return Err(ConfigError::NotFound.into());
+    }
+    write_config(&config)
+}
+
+pub fn set_provider(provider: String) -> Result<()> {
+    let mut config = read_config_file()?;
+    config.provider = Some(provider);
+    write_config(&config)
+}
+
+// None removes the command.
+pub fn set_alert_command(command: Option<String>) -> Result<()> {
+    let mut config = read_config_file()?;
+    config.alert_command = command;
+    write_config(&config)
+}
+
+// None removes the webhook.
+pub fn set_alert_webhook(url: Option<String>) -> Result<()> {
+    let mut config = read_config_file()?;
+    config.alert_webhook = url;
+    write_config(&config)
+}
+
+// None goes back to the default thresholds.
+pub fn set_quota_warning_thresholds(thresholds: Option<String>) -> Result<()> {
+    let mut config = read_config_file()?;
+    config.quota_warning_thresholds = thresholds;
+    write_config(&config)
+}
@@ -0,0 +1,323 @@
+use anyhow::Result;
+use thiserror::Error;
+use crate::engine::RATE_DISPLAY_DECIMALS;
+
+// Built-in registry of currencies, based on ISO 4217.
+// Also contains the few non-ISO codes used by ExchangeRate-API for local currencies (e.g. GGP, FOK), which have no numeric code.
+
+pub struct Currency {
+    pub code: &'static str,
+    pub numeric: Option<u16>,
+    pub name: &'static str,
+    pub symbol: &'static str,
+    // Number of decimal places of the minor unit, e.g. 2 for cents, 0 for JPY.
+    // None when ISO 4217 gives the currency no minor unit ("N.A."), e.g. XDR.
+    pub minor_units: Option<u32>,
+    pub countries: &'static [&'static str],
+}
+
+#[derive(Error, Debug)]
+enum CurrencyError {
+    #[error("Unknown currency code: {0}.")]
+    UnknownCode(String),
+}
+
+impl Currency {
+    pub fn numeric_code(&self) -> String {
+        match self.numeric {
+            Some(numeric) => format!("{:03}", numeric),
+            None => "none (not an ISO 4217 currency)".to_string(),
+        }
+    }
+
+    pub fn minor_units_description(&self) -> String {
+        match self.minor_units {
+            Some(minor_units) => minor_units.to_string(),
+            None => "none (no minor unit)".to_string(),
+        }
+    }
+
+    // Decimal places amounts are rounded to. Currencies without a minor unit keep the precision of the rates.
+    pub fn rounding_decimals(&self) -> u32 {
+        self.minor_units.unwrap_or(RATE_DISPLAY_DECIMALS)
+    }
+}
+
+const fn currency(
+    code: &'static str,
+    numeric: Option<u16>,
+    name: &'static str,
+    symbol: &'static str,
+    minor_units: Option<u32>,
+    countries: &'static [&'static str],
+) -> Currency {
+    Currency { code, numeric, name, symbol, minor_units, countries }
+}
+
+pub static CURRENCIES: &[Currency] = &[
+    currency("AED", Some(784), "UAE Dirham", "د.إ", Some(2), &["United Arab Emirates"]),
+    currency("AFN", Some(971), "Afghani", "؋", Some(2), &["Afghanistan"]),
+    currency("ALL", Some(8), "Lek", "L", Some(2), &["Albania"]),
+    currency("AMD", Some(51), "Armenian Dram", "֏", Some(2), &["Armenia"]),
+    currency("ANG", Some(532), "Netherlands Antillean Guilder", "ƒ", Some(2), &["Curaçao", "Sint Maarten"]),
+    currency("AOA", Some(973), "Kwanza", "Kz", Some(2), &["Angola"]),
+    currency("ARS", Some(32), "Argentine Peso", "$", Some(2), &["Argentina"]),
+    currency("AUD", Some(36), "Australian Dollar", "A$", Some(2), &["Australia", "Christmas Island", "Cocos (Keeling) Islands", "Heard Island and McDonald Islands", "Kiribati", "Nauru", "Norfolk Island", "Tuvalu"]),
+    currency("AWG", Some(533), "Aruban Florin", "ƒ", Some(2), &["Aruba"]),
+    currency("AZN", Some(944), "Azerbaijan Manat", "₼", Some(2), &["Azerbaijan"]),
+    currency("BAM", Some(977), "Convertible Mark", "KM", Some(2), &["Bosnia and Herzegovina"]),
+    currency("BBD", Some(52), "Barbados Dollar", "Bds$", Some(2), &["Barbados"]),
+    currency("BDT", Some(50), "Taka", "৳", Some(2), &["Bangladesh"]),
+    currency("BGN", Some(975), "Bulgarian Lev", "лв", Some(2), &["Bulgaria"]),
+    currency("BHD", Some(48), "Bahraini Dinar", ".د.ب", Some(3), &["Bahrain"]),
+    currency("BIF", Some(108), "Burundi Franc", "FBu", Some(0), &["Burundi"]),
+    currency("BMD", Some(60), "Bermudian Dollar", "$", Some(2), &["Bermuda"]),
+    currency("BND", Some(96), "Brunei Dollar", "B$", Some(2), &["Brunei Darussalam"]),
+    currency("BOB", Some(68), "Boliviano", "Bs", Some(2), &["Bolivia"]),
+    currency("BRL", Some(986), "Brazilian Real", "R$", Some(2), &["Brazil"]),
+    currency("BSD", Some(44), "Bahamian Dollar", "B$", Some(2), &["Bahamas"]),
+    currency("BTN", Some(64), "Ngultrum", "Nu.", Some(2), &["Bhutan"]),
+    currency("BWP", Some(72), "Pula", "P", Some(2), &["Botswana"]),
+    currency("BYN", Some(933), "Belarusian Ruble", "Br", Some(2), &["Belarus"]),
+    currency("BZD", Some(84), "Belize Dollar", "BZ$", Some(2), &["Belize"]),
+    currency("CAD", Some(124), "Canadian Dollar", "C$", Some(2), &["Canada"]),
+    currency("CDF", Some(976), "Congolese Franc", "FC", Some(2), &["Democratic Republic of the Congo"]),
+    currency("CHF", Some(756), "Swiss Franc", "CHF", Some(2), &["Switzerland", "Liechtenstein"]),
+    currency("CLP", Some(152), "Chilean Peso", "$", Some(0), &["Chile"]),
+    currency("CNY", Some(156), "Yuan Renminbi", "¥", Some(2), &["China"]),
+    currency("COP", Some(170), "Colombian Peso", "$", Some(2), &["Colombia"]),
+    currency("CRC", Some(188), "Costa Rican Colon", "₡", Some(2), &["Costa Rica"]),
+    currency("CUP", Some(192), "Cuban Peso", "$", Some(2), &["Cuba"]),
+    currency("CVE", Some(132), "Cabo Verde Escudo", "Esc", Some(2), &["Cabo Verde"]),
+    currency("CZK", Some(203), "Czech Koruna", "Kč", Some(2), &["Czechia"]),
+    currency("DJF", Some(262), "Djibouti Franc", "Fdj", Some(0), &["Djibouti"]),
+    currency("DKK", Some(208), "Danish Krone", "kr", Some(2), &["Denmark", "Faroe Islands", "Greenland"]),
+    currency("DOP", Some(214), "Dominican Peso", "RD$", Some(2), &["Dominican Republic"]),
+    currency("DZD", Some(12), "Algerian Dinar", "دج", Some(2), &["Algeria"]),
+    currency("EGP", Some(818), "Egyptian Pound", "E£", Some(2), &["Egypt"]),
+    currency("ERN", Some(232), "Nakfa", "Nfk", Some(2), &["Eritrea"]),
+    currency("ETB", Some(230), "Ethiopian Birr", "Br", Some(2), &["Ethiopia"]),
+    currency("EUR", Some(978), "Euro", "€", Some(2), &["Andorra", "Austria", "Belgium", "Croatia", "Cyprus", "Estonia", "Finland", "France", "Germany", "Greece", "Ireland", "Italy", "Kosovo", "Latvia", "Lithuania", "Luxembourg", "Malta", "Monaco", "Montenegro", "Netherlands", "Portugal", "San Marino", "Slovakia", "Slovenia", "Spain", "Vatican City"]),
+    currency("FJD", Some(242), "Fiji Dollar", "FJ$", Some(2), &["Fiji"]),
+    currency("FKP", Some(238), "Falkland Islands Pound", "£", Some(2), &["Falkland Islands"]),
+    currency("FOK", None, "Faroese Króna", "kr", Some(2), &["Faroe Islands"]),
+    currency("GBP", Some(826), "Pound Sterling", "£", Some(2), &["United Kingdom", "Guernsey", "Isle of Man", "Jersey"]),
+    currency("GEL", Some(981), "Lari", "₾", Some(2), &["Georgia"]),
+    currency("GGP", None, "Guernsey Pound", "£", Some(2), &["Guernsey"]),
+    currency("GHS", Some(936), "Ghana Cedi", "₵", Some(2), &["Ghana"]),
+    currency("GIP", Some(292), "Gibraltar Pound", "£", Some(2), &["Gibraltar"]),
+    currency("GMD", Some(270), "Dalasi", "D", Some(2), &["Gambia"]),
+    currency("GNF", Some(324), "Guinean Franc", "FG", Some(0), &["Guinea"]),
+    currency("GTQ", Some(320), "Quetzal", "Q", Some(2), &["Guatemala"]),
+    currency("GYD", Some(328), "Guyana Dollar", "G$", Some(2), &["Guyana"]),
+    currency("HKD", Some(344), "Hong Kong Dollar", "HK$", Some(2), &["Hong Kong"]),
+    currency("HNL", Some(340), "Lempira", "L", Some(2), &["Honduras"]),
+    currency("HRK", Some(191), "Kuna", "kn", Some(2), &["Croatia (until 2023)"]),
+    currency("HTG", Some(332), "Gourde", "G", Some(2), &["Haiti"]),
+    currency("HUF", Some(348), "Forint", "Ft", Some(2), &["Hungary"]),
+    currency("IDR", Some(360), "Rupiah", "Rp", Some(2), &["Indonesia"]),
+    currency("ILS", Some(376), "New Israeli Sheqel", "₪", Some(2), &["Israel"]),
+    currency("IMP", None, "Manx Pound", "£", Some(2), &["Isle of Man"]),
+    currency("INR", Some(356), "Indian Rupee", "₹", Some(2), &["India", "Bhutan"]),
+    currency("IQD", Some(368), "Iraqi Dinar", "ع.د", Some(3), &["Iraq"]),
+    currency("IRR", Some(364), "Iranian Rial", "﷼", Some(2), &["Iran"]),
+    currency("ISK", Some(352), "Iceland Krona", "kr", Some(0), &["Iceland"]),
+    currency("JEP", None, "Jersey Pound", "£", Some(2), &["Jersey"]),
+    currency("JMD", Some(388), "Jamaican Dollar", "J$", Some(2), &["Jamaica"]),
+    currency("JOD", Some(400), "Jordanian Dinar", "د.ا", Some(3), &["Jordan"]),
+    currency("JPY", Some(392), "Yen", "¥", Some(0), &["Japan"]),
+    currency("KES", Some(404), "Kenyan Shilling", "KSh", Some(2), &["Kenya"]),
+    currency("KGS", Some(417), "Som", "с", Some(2), &["Kyrgyzstan"]),
+    currency("KHR", Some(116), "Riel", "៛", Some(2), &["Cambodia"]),
+    currency("KID", None, "Kiribati Dollar", "$", Some(2), &["Kiribati"]),
+    currency("KMF", Some(174), "Comorian Franc", "CF", Some(0), &["Comoros"]),
+    currency("KPW", Some(408), "North Korean Won", "₩", Some(2), &["North Korea"]),
+    currency("KRW", Some(410), "Won", "₩", Some(0), &["South Korea"]),
+    currency("KWD", Some(414), "Kuwaiti Dinar", "د.ك", Some(3), &["Kuwait"]),
+    currency("KYD", Some(136), "Cayman Islands Dollar", "CI$", Some(2), &["Cayman Islands"]),
+    currency("KZT", Some(398), "Tenge", "₸", Some(2), &["Kazakhstan"]),
+    currency("LAK", Some(418), "Lao Kip", "₭", Some(2), &["Laos"]),
+    currency("LBP", Some(422), "Lebanese Pound", "ل.ل", Some(2), &["Lebanon"]),
+    currency("LKR", Some(144), "Sri Lanka Rupee", "Rs", Some(2), &["Sri Lanka"]),
+    currency("LRD", Some(430), "Liberian Dollar", "L$", Some(2), &["Liberia"]),
+    currency("LSL", Some(426), "Loti", "L", Some(2), &["Lesotho"]),
+    currency("LYD", Some(434), "Libyan Dinar", "ل.د", Some(3), &["Libya"]),
+    currency("MAD", Some(504), "Moroccan Dirham", "د.م.", Some(2), &["Morocco", "Western Sahara"]),
+    currency("MDL", Some(498), "Moldovan Leu", "L", Some(2), &["Moldova"]),
+    currency("MGA", Some(969), "Malagasy Ariary", "Ar", Some(2), &["Madagascar"]),
+    currency("MKD", Some(807), "Denar", "ден", Some(2), &["North Macedonia"]),
+    currency("MMK", Some(104), "Kyat", "K", Some(2), &["Myanmar"]),
+    currency("MNT", Some(496), "Tugrik", "₮", Some(2), &["Mongolia"]),
+    currency("MOP", Some(446), "Pataca", "MOP$", Some(2), &["Macao"]),
+    currency("MRU", Some(929), "Ouguiya", "UM", Some(2), &["Mauritania"]),
+    currency("MUR", Some(480), "Mauritius Rupee", "₨", Some(2), &["Mauritius"]),
+    currency("MVR", Some(462), "Rufiyaa", "Rf", Some(2), &["Maldives"]),
+    currency("MWK", Some(454), "Malawi Kwacha", "MK", Some(2), &["Malawi"]),
+    currency("MXN", Some(484), "Mexican Peso", "$", Some(2), &["Mexico"]),
+    currency("MYR", Some(458), "Malaysian Ringgit", "RM", Some(2), &["Malaysia"]),
+    currency("MZN", Some(943), "Mozambique Metical", "MT", Some(2), &["Mozambique"]),
+    currency("NAD", Some(516), "Namibia Dollar", "N$", Some(2), &["Namibia"]),
+    currency("NGN", Some(566), "Naira", "₦", Some(2), &["Nigeria"]),
+    currency("NIO", Some(558), "Cordoba Oro", "C$", Some(2), &["Nicaragua"]),
+    currency("NOK", Some(578), "Norwegian Krone", "kr", Some(2), &["Norway", "Svalbard and Jan Mayen", "Bouvet Island"]),
+    currency("NPR", Some(524), "Nepalese Rupee", "Rs", Some(2), &["Nepal"]),
+    currency("NZD", Some(554), "New Zealand Dollar", "NZ$", Some(2), &["New Zealand", "Cook Islands", "Niue", "Pitcairn Islands", "Tokelau"]),
+    currency("OMR", Some(512), "Rial Omani", "ر.ع.", Some(3), &["Oman"]),
+    currency("PAB", Some(590), "Balboa", "B/.", Some(2), &["Panama"]),
+    currency("PEN", Some(604), "Sol", "S/", Some(2), &["Peru"]),
+    currency("PGK", Some(598), "Kina", "K", Some(2), &["Papua New Guinea"]),
+    currency("PHP", Some(608), "Philippine Peso", "₱", Some(2), &["Philippines"]),
+    currency("PKR", Some(586), "Pakistan Rupee", "Rs", Some(2), &["Pakistan"]),
+    currency("PLN", Some(985), "Zloty", "zł", Some(2), &["Poland"]),
+    currency("PYG", Some(600), "Guarani", "₲", Some(0), &["Paraguay"]),
+    currency("QAR", Some(634), "Qatari Rial", "ر.ق", Some(2), &["Qatar"]),
+    currency("RON", Some(946), "Romanian Leu", "lei", Some(2), &["Romania"]),
+    currency("RSD", Some(941), "Serbian Dinar", "дин", Some(2), &["Serbia"]),
+    currency("RUB", Some(643), "Russian Ruble", "₽", Some(2), &["Russia"]),
+    currency("RWF", Some(646), "Rwanda Franc", "FRw", Some(0), &["Rwanda"]),
+    currency("SAR", Some(682), "Saudi Riyal", "ر.س", Some(2), &["Saudi Arabia"]),
+    currency("SBD", Some(90), "Solomon Islands Dollar", "SI$", Some(2), &["Solomon Islands"]),
+    currency("SCR", Some(690), "Seychelles Rupee", "SR", Some(2), &["Seychelles"]),
+    currency("SDG", Some(938), "Sudanese Pound", "ج.س.", Some(2), &["Sudan"]),
+    currency("SEK", Some(752), "Swedish Krona", "kr", Some(2), &["Sweden"]),
+    currency("SGD", Some(702), "Singapore Dollar", "S$", Some(2), &["Singapore"]),
+    currency("SHP", Some(654), "Saint Helena Pound", "£", Some(2), &["Saint Helena, Ascension and Tristan da Cunha"]),
+    currency("SLE", Some(925), "Leone", "Le", Some(2), &["Sierra Leone"]),
+    currency("SLL", Some(694), "Leone (old)", "Le", Some(2), &["Sierra Leone (until 2024)"]),
+    currency("SOS", Some(706), "Somali Shilling", "Sh", Some(2), &["Somalia"]),
+    currency("SRD", Some(968), "Surinam Dollar", "$", Some(2), &["Suriname"]),
+    currency("SSP", Some(728), "South Sudanese Pound", "£", Some(2), &["South Sudan"]),
+    currency("STN", Some(930), "Dobra", "Db", Some(2), &["Sao Tome and Principe"]),
+    currency("SVC", Some(222), "El Salvador Colon", "₡", Some(2), &["El Salvador"]),
+    currency("SYP", Some(760), "Syrian Pound", "£S", Some(2), &["Syria"]),
+    currency("SZL", Some(748), "Lilangeni", "E", Some(2), &["Eswatini"]),
+    currency("THB", Some(764), "Baht", "฿", Some(2), &["Thailand"]),
+    currency("TJS", Some(972), "Somoni", "SM", Some(2), &["Tajikistan"]),
+    currency("TMT", Some(934), "Turkmenistan New Manat", "m", Some(2), &["Turkmenistan"]),
+    currency("TND", Some(788), "Tunisian Dinar", "د.ت", Some(3), &["Tunisia"]),
+    currency("TOP", Some(776), "Pa'anga", "T$", Some(2), &["Tonga"]),
+    currency("TRY", Some(949), "Turkish Lira", "₺", Some(2), &["Türkiye"]),
+    currency("TTD", Some(780), "Trinidad and Tobago Dollar", "TT$", Some(2), &["Trinidad and Tobago"]),
+    currency("TVD", None, "Tuvaluan Dollar", "$", Some(2), &["Tuvalu"]),
+    currency("TWD", Some(901), "New Taiwan Dollar", "NT$", Some(2), &["Taiwan"]),
+    currency("TZS", Some(834), "Tanzanian Shilling", "TSh", Some(2), &["Tanzania"]),
+    currency("UAH", Some(980), "Hryvnia", "₴", Some(2), &["Ukraine"]),
+    currency("UGX", Some(800), "Uganda Shilling", "USh", Some(0), &["Uganda"]),
+    currency("USD", Some(840), "US Dollar", "$", Some(2), &["United States", "American Samoa", "Bonaire, Sint Eustatius and Saba", "British Indian Ocean Territory", "Ecuador", "El Salvador", "Guam", "Marshall Islands", "Micronesia", "Northern Mariana Islands", "Palau", "Panama", "Puerto Rico", "Timor-Leste", "Turks and Caicos Islands", "British Virgin Islands", "U.S. Virgin Islands"]),
+    currency("UYU", Some(858), "Peso Uruguayo", "$U", Some(2), &["Uruguay"]),
+    currency("UZS", Some(860), "Uzbekistan Sum", "soʻm", Some(2), &["Uzbekistan"]),
+    currency("VES", Some(928), "Bolívar Soberano", "Bs.S", Some(2), &["Venezuela"]),
+    currency("VND", Some(704), "Dong", "₫", Some(0), &["Vietnam"]),
+    currency("VUV", Some(548), "Vatu", "VT", Some(0), &["Vanuatu"]),
+    currency("WST", Some(882), "Tala", "WS$", Some(2), &["Samoa"]),
+    currency("XAF", Some(950), "CFA Franc BEAC", "FCFA", Some(0), &["Cameroon", "Central African Republic", "Chad", "Republic of the Congo", "Equatorial Guinea", "Gabon"]),
+    currency("XCD", Some(951), "East Caribbean Dollar", "EC$", Some(2), &["Anguilla", "Antigua and Barbuda", "Dominica", "Grenada", "Montserrat", "Saint Kitts and Nevis", "Saint Lucia", "Saint Vincent and the Grenadines"]),
+    currency("XCG", Some(532), "Caribbean Guilder", "Cg", Some(2), &["Curaçao", "Sint Maarten"]),
+    // ISO 4217 doesn't define a minor unit for the SDR, 2 decimal places are used.
+    currency("XDR", Some(960), "SDR (Special Drawing Right)", "SDR", None, &["International Monetary Fund"]),
+    currency("XOF", Some(952), "CFA Franc BCEAO", "CFA", Some(0), &["Benin", "Burkina Faso", "Côte d'Ivoire", "Guinea-Bissau", "Mali", "Niger", "Senegal", "Togo"]),
+    currency("XPF", Some(953), "CFP Franc", "₣", Some(0), &["French Polynesia", "New Caledonia", "Wallis and Futuna"]),
+    currency("YER", Some(886), "Yemeni Rial", "﷼", Some(2), &["Yemen"]),
+    currency("ZAR", Some(710), "Rand", "R", Some(2), &["South Africa", "Lesotho", "Namibia"]),
+    currency("ZMW", Some(967), "Zambian Kwacha", "ZK", Some(2), &["Zambia"]),
+    currency("ZWG", Some(924), "Zimbabwe Gold", "ZiG", Some(2), &["Zimbabwe"]),
+    currency("ZWL", Some(932), "Zimbabwe Dollar", "Z$", Some(2), &["Zimbabwe (until 2024)"]),
+];
+
+pub fn find_currency(code: &str) -> Option<&'static Currency> {
+    CURRENCIES.iter().find(|currency| currency.code.eq_ignore_ascii_case(code))
+}
+
+// Symbols used by several currencies mean the most traded one, other shared symbols (e.g. kr) aren't recognized.
+const PREFERRED_SYMBOLS: &[(&str, &str)] = &[("$", "USD"), ("£", "GBP"), ("¥", "JPY"), ("₩", "KRW")];
+
+pub fn find_currency_by_symbol(symbol: &str) -> Option<&'static Currency> {
+    if let Some((_, code)) = PREFERRED_SYMBOLS.iter().find(|(preferred, _)| *preferred == symbol) {
+        return find_currency(code);
+    }
+    let mut matching = CURRENCIES.iter().filter(|currency| currency.symbol.to_lowercase() == symbol.to_lowercase());
+    match (matching.next(), matching.next()) {
+        (Some(currency), None) => Some(currency),
+        _ => None,
+    }
+}
+
+// A currency written as its code (in any case) or its symbol, e.g. eur, € or zł.
+pub fn resolve_currency(code_or_symbol: &str) -> Option<&'static Currency> {
+    find_currency(code_or_symbol).or_else(|| find_currency_by_symbol(code_or_symbol))
+}
+
+// Checks the code before any request is made, instead of waiting for the provider to reject it.
+pub fn validate_currency(code: &str) -> Result<&'static Currency> {
+    match find_currency(code) {
+        Some(currency) => Ok(currency),
+        None => Err(CurrencyError::UnknownCode(code.to_uppercase()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::{find_currency, resolve_currency, validate_currency, CURRENCIES};
+
+    #[test]
+    fn test_find_currency() {
+        let euro = find_currency("EUR").unwrap();
+        assert_eq!(euro.name, "Euro");
+        assert_eq!(euro.symbol, "€");
+        assert_eq!(euro.numeric_code(), "978");
+        assert!(euro.countries.contains(&"Germany"));
+        assert_eq!(find_currency("usd").unwrap().code, "USD");
+        assert!(find_currency("UST").is_none());
+    }
+
+    #[test]
+    fn test_resolve_currency() {
+        assert_eq!(resolve_currency("eur").unwrap().code, "EUR");
+        assert_eq!(resolve_currency("€").unwrap().code, "EUR");
+        assert_eq!(resolve_currency("$").unwrap().code, "USD");
+        assert_eq!(resolve_currency("£").unwrap().code, "GBP");
+        assert_eq!(resolve_currency("ZŁ").unwrap().code, "PLN");
+        assert_eq!(resolve_currency("₹").unwrap().code, "INR");
+        // Used by several currencies, none of them the obvious one.
+        assert!(resolve_currency("kr").is_none());
+        assert!(resolve_currency("to").is_none());
+    }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(find_currency("JPY").unwrap().minor_units, Some(0));
+        assert_eq!(find_currency("KWD").unwrap().minor_units, Some(3));
+        assert_eq!(find_currency("USD").unwrap().minor_units, Some(2));
+        // ISO 4217 gives the SDR no minor unit.
+        let xdr = find_currency("XDR").unwrap();
+        assert_eq!(xdr.minor_units, None);
+        assert_eq!(xdr.rounding_decimals(), 10);
+        assert_eq!(xdr.minor_units_description(), "none (no minor unit)");
+    }
+
+    #[test]
+    fn test_numeric_code() {
+        assert_eq!(find_currency("ALL").unwrap().numeric_code(), "008");
+        assert_eq!(find_currency("GGP").unwrap().numeric_code(), "none (not an ISO 4217 currency)");
+    }
+
+    #[test]
+    fn test_validate_currency() {
+        assert!(validate_currency("gbp").is_ok());
+        match validate_currency("eux") {
+            Ok(_) => panic!("Expected an error, but got a currency."),
+            Err(e) => assert_eq!(e.to_string(), "Unknown currency code: EUX."),
+        }
+    }
+
+    #[test]
+    fn test_registry_is_consistent() {
+        let mut codes = HashSet::new();
+        for currency in CURRENCIES {
+            assert!(codes.insert(currency.code), "{} is listed twice", currency.code);
+            assert!(currency.code.len() == 3 && currency.code.chars().all(|c| c.is_ascii_uppercase()));
+            assert!(!currency.countries.is_empty());
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::str::FromStr;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, Utc, Weekday};
+use thiserror::Error;
+use crate::api::{ApiClient, ApiMultirateResponse, ApiRateResponse, RequestError};
+use crate::config::read_config;
+use crate::currency::find_currency;
+use crate::provider::{pair_from_table, RateProvider};
+use crate::rate_table::RateTable;
+use crate::retry::RetryPolicy;
+
+// Euro foreign exchange reference rates published by the European Central Bank.
+// No API key is needed, but only about 30 currencies are available and the rates are updated once per working day.
+
+// The files are requested through the same client as ExchangeRate-API, with the same retries.
+pub const BASE_ECB_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref";
+const DAILY_FILE: &str = "eurofxref-daily.xml";
+const HISTORY_90_DAYS_FILE: &str = "eurofxref-hist-90d.xml";
+// All reference rates since 1999, a few megabytes, so it's only used for dates the 90 day file doesn't cover.
+const HISTORY_FILE: &str = "eurofxref-hist.xml";
+// The 90 day file covers about 90 calendar days, older dates are looked up in the full history to be safe.
+const HISTORY_90_DAYS_MAX_AGE: i64 = 80;
+const DOCUMENTATION_URL: &str = "https://www.ecb.europa.eu/stats/policy_and_exchange_rates/euro_reference_exchange_rates/html/index.en.html";
+const TERMS_OF_USE_URL: &str = "https://www.ecb.europa.eu/services/disclaimer/html/index.en.html";
+
+// The reference rates are published around 16:00 CET, 15:00 UTC is used as the update time.
+const PUBLICATION_HOUR_UTC: u32 = 15;
+
+#[derive(Error, Debug)]
+enum EcbError {
+    #[error("Error parsing ECB reference rates.")]
+    ParseError,
+}
+
+// Rates for a single day, with EUR as the base currency.
+struct ReferenceDay {
+    date: NaiveDate,
+    rates: RateTable,
+}
+
+fn parse_reference_days(xml: &str) -> Result<Vec<ReferenceDay>> {
+    let document = match roxmltree::Document::parse(xml) {
+        Ok(document) => document,
+        Err(_) => return Err(EcbError::ParseError.into()),
+    };
+    let mut days = Vec::new();
+    for day_node in document.descendants().filter(|node| node.has_tag_name("Cube") && node.has_attribute("time")) {
+        let date = match day_node.attribute("time").map(|time| NaiveDate::parse_from_str(time, "%Y-%m-%d")) {
+            Some(Ok(date)) => date,
+            _ => return Err(EcbError::ParseError.into()),
+        };
+        let mut rates = RateTable::new();
+        rates.insert("EUR", Decimal::ONE)?;
+        for rate_node in day_node.children().filter(|node| node.has_tag_name("Cube")) {
+            let currency = rate_node.attribute("currency");
+            let rate = rate_node.attribute("rate").map(Decimal::from_str);
+            match (currency, rate) {
+                (Some(currency), Some(Ok(rate))) if rates.insert(currency, rate).is_ok() => (),
+                _ => return Err(EcbError::ParseError.into()),
+            }
+        }
+        days.push(ReferenceDay { date, rates });
+    }
+    if days.is_empty() {
+        return Err(EcbError::ParseError.into());
+    }
+    Ok(days)
+}
+
+fn publication_time(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(PUBLICATION_HOUR_UTC, 0, 0).unwrap_or_default().and_utc()
+}
+
+// No rates are published on weekends.
+fn next_publication_date(date: NaiveDate) -> NaiveDate {
+    let days = match date.weekday() {
+        Weekday::Fri => 3,
+        Weekday::Sat => 2,
+        _ => 1,
+    };
+    date + Days::new(days)
+}
+
+fn rebase(day: &ReferenceDay, base_currency: &str) -> Result<ApiMultirateResponse> {
+    let conversion_rates = match day.rates.rebased(base_currency) {
+        Some(conversion_rates) => conversion_rates,
+        None => return Err(RequestError::UnsupportedCurrency(None).into()),
+    };
+    let last_update = publication_time(day.date);
+    let next_update = publication_time(next_publication_date(day.date));
+    Ok(ApiMultirateResponse {
+        result: "success".to_string(),
+        documentation: DOCUMENTATION_URL.to_string(),
+        terms_of_use: TERMS_OF_USE_URL.to_string(),
+        time_last_update_unix: last_update.timestamp(),
+        time_last_update_utc: last_update.to_rfc2822(),
+        time_next_update_unix: next_update.timestamp(),
+        time_next_update_utc: next_update.to_rfc2822(),
+        base_code: base_currency.to_string(),
+        conversion_rates,
+    })
+}
+
+// Parses any of the ECB reference rate files (daily, last 90 days or the full history).
+// Returns one table per day, in the order they appear in the file (newest first).
+pub fn parse_reference_rates(xml: &str, base_currency: &str) -> Result<Vec<ApiMultirateResponse>> {
+    parse_reference_days(xml)?
+        .iter()
+        .map(|day| rebase(day, base_currency))
+        .collect()
+}
+
+// Rates in effect on the given date. No rates are published on weekends and holidays,
+// so the last publication on or before the date is used, the same way the ECB rates are applied.
+pub fn reference_rates_on(xml: &str, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse> {
+    let days = parse_reference_days(xml)?;
+    match days.iter().find(|day| day.date <= date) {
+        Some(day) => rebase(day, base_currency),
+        None => Err(RequestError::NoDataAvailable(None).into()),
+    }
+}
+
+pub struct Ecb {
+    client: ApiClient,
+    base_url: String,
+}
+
+impl Ecb {
+    pub fn new(base_url: &str, retry_policy: RetryPolicy) -> Result<Ecb> {
+        Ok(Ecb {
+            client: ApiClient::new(retry_policy)?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    pub fn from_config() -> Result<Ecb> {
+        let config = read_config()?;
+        Ecb::new(BASE_ECB_URL, RetryPolicy::from_config(&config))
+    }
+
+    async fn fetch_xml(&self, file: &str) -> Result<String> {
+        self.client.get_text(&format!("{}/{}", self.base_url, file)).await
+    }
+
+    async fn fetch(&self, file: &str, base_currency: &str) -> Result<Vec<ApiMultirateResponse>> {
+        let xml = self.fetch_xml(file).await?;
+        parse_reference_rates(&xml, base_currency)
+    }
+}
+
+#[async_trait]
+impl RateProvider for Ecb {
+    fn name(&self) -> &'static str {
+        "ecb"
+    }
+
+    fn reference_currency(&self) -> &'static str {
+        "EUR"
+    }
+
+    async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse> {
+        match self.fetch(DAILY_FILE, base_currency).await?.into_iter().next() {
+            Some(table) => Ok(table),
+            None => Err(EcbError::ParseError.into()),
+        }
+    }
+
+    async fn pair(&self, from: &str, to: &str) -> Result<ApiRateResponse> {
+        let table = self.latest(from).await?;
+        pair_from_table(table, from, to)
+    }
+
+    async fn history(&self, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse> {
+        let today = Utc::now().date_naive();
+        if date > today {
+            return Err(RequestError::NoDataAvailable(None).into());
+        }
+        let file = if today - date <= Duration::days(HISTORY_90_DAYS_MAX_AGE) {
+            HISTORY_90_DAYS_FILE
+        } else {
+            HISTORY_FILE
+        };
+        let xml = self.fetch_xml(file).await?;
+        reference_rates_on(&xml, base_currency, date)
+    }
+
+    async fn supported_codes(&self) -> Result<Vec<(String, String)>> {
+        let table = self.latest("EUR").await?;
+        let codes = table.conversion_rates.codes().map(|code| {
+            let name = find_currency(code).map(|currency| currency.name).unwrap_or_default();
+            (code.to_string(), name.to_string())
+        }).collect();
+        Ok(codes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use chrono::NaiveDate;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::{parse_reference_rates, reference_rates_on, Ecb};
+    use crate::api::{ApiMultirateResponse, RequestError};
+    use crate::provider::RateProvider;
+    use crate::retry::RetryPolicy;
+    use crate::test_server::{TestServer, DROP_CONNECTION};
+    use crate::test_support::{decimal, DAILY_XML, HISTORY_XML};
+
+
+    fn rate(table: &ApiMultirateResponse, currency: &str) -> Decimal {
+        table.conversion_rates.get(currency).unwrap()
+    }
+
+    #[test]
+    fn test_parse_daily_eur() {
+        let tables = parse_reference_rates(DAILY_XML, "EUR").unwrap();
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!(table.base_code, "EUR");
+        assert_eq!(table.result, "success");
+        assert_eq!(table.time_last_update_utc, "Fri, 15 Mar 2024 15:00:00 +0000");
+        assert_eq!(table.time_next_update_utc, "Mon, 18 Mar 2024 15:00:00 +0000");
+        assert_eq!(rate(table, "EUR"), decimal("1"));
+        assert_eq!(rate(table, "USD"), decimal("1.0892"));
+        assert_eq!(table.conversion_rates.codes().count(), 31);
+    }
+
+    #[test]
+    fn test_parse_daily_other_base() {
+        let table = &parse_reference_rates(DAILY_XML, "USD").unwrap()[0];
+        assert_eq!(table.base_code, "USD");
+        assert_eq!(rate(table, "USD"), decimal("1"));
+        assert_eq!(rate(table, "EUR"), Decimal::ONE / decimal("1.0892"));
+        assert_eq!(rate(table, "JPY"), decimal("162.33") / decimal("1.0892"));
+    }
+
+    #[test]
+    fn test_parse_daily_unsupported_base() {
+        match parse_reference_rates(DAILY_XML, "UST") {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "Unsupported currency."),
+        }
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let tables = parse_reference_rates(HISTORY_XML, "EUR").unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].time_last_update_utc, "Fri, 15 Mar 2024 15:00:00 +0000");
+        assert_eq!(tables[2].time_last_update_utc, "Wed, 13 Mar 2024 15:00:00 +0000");
+        assert_eq!(tables[2].time_next_update_utc, "Thu, 14 Mar 2024 15:00:00 +0000");
+        assert_eq!(rate(&tables[1], "PLN"), decimal("4.2833"));
+    }
+
+    #[test]
+    fn test_rates_on_date() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let table = reference_rates_on(HISTORY_XML, "EUR", date(14)).unwrap();
+        assert_eq!(table.time_last_update_utc, "Thu, 14 Mar 2024 15:00:00 +0000");
+        assert_eq!(rate(&table, "USD"), decimal("1.0925"));
+        // Saturday, the rates published on Friday are used.
+        let table = reference_rates_on(HISTORY_XML, "USD", date(16)).unwrap();
+        assert_eq!(table.time_last_update_utc, "Fri, 15 Mar 2024 15:00:00 +0000");
+        assert_eq!(rate(&table, "PLN"), decimal("4.2933") / decimal("1.0892"));
+        match reference_rates_on(HISTORY_XML, "EUR", date(12)) {
+            Ok(_) => panic!("Expected an error, but got a response."),
+            Err(e) => assert_eq!(e.to_string(), "No data available for the date."),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_xml() {
+        assert!(parse_reference_rates("<html>Not found</html>", "EUR").is_err());
+        assert!(parse_reference_rates("not xml at all", "EUR").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let calls = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => (503, "<html>Service Unavailable</html>".to_string()),
+                1 => (DROP_CONNECTION, String::new()),
+                _ => (200, DAILY_XML.to_string()),
+            }
+        }).await;
+        let ecb = Ecb::new(&server.url(), RetryPolicy::without_delay(3)).unwrap();
+        let table = ecb.latest("EUR").await.unwrap();
+        assert_eq!(rate(&table, "USD"), decimal("1.0892"));
+        assert_eq!(server.requests().len(), 3);
+        assert!(server.requests().iter().all(|request| request.path == "/eurofxref-daily.xml"));
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_for_missing_file() {
+        let server = TestServer::start(|_| (404, "<html>Not found</html>".to_string())).await;
+        let ecb = Ecb::new(&server.url(), RetryPolicy::without_delay(3)).unwrap();
+        let error = ecb.latest("EUR").await.err().unwrap().downcast::<RequestError>().unwrap();
+        assert_eq!(error.response().unwrap().status, 404);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
@@ -0,0 +1,245 @@
+use std::str::FromStr;
+use serde_json::Value;
+use anyhow::Result;
+use rust_decimal::{Decimal, RoundingStrategy};
+use thiserror::Error;
+use crate::api::{ApiMultirateResponse, ApiRateResponse, RequestError};
+
+// Derives the rate between any two currencies from a single table of rates.
+// A table with base C gives rate(C -> X) for every X, so rate(A -> B) = rate(C -> B) / rate(C -> A).
+// All the arithmetic is done with decimals, so amounts and rates are never affected by binary floating point errors.
+
+// Number of decimal places shown for rates, the calculations always use the full precision.
+pub const RATE_DISPLAY_DECIMALS: u32 = 10;
+
+#[derive(Error, Debug)]
+enum EngineError {
+    #[error("Amount is too large to convert.")]
+    Overflow,
+    #[error("Unknown rounding mode. Available modes: half-even, half-up, truncate.")]
+    UnknownRoundingMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoundingMode {
+    // Ties go to the even neighbour (banker's rounding), 2.345 -> 2.34.
+    HalfEven,
+    // Ties go away from zero, 2.345 -> 2.35.
+    HalfUp,
+    // Extra digits are dropped, 2.349 -> 2.34.
+    Truncate,
+}
+
+impl FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<RoundingMode> {
+        match mode {
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "half-up" => Ok(RoundingMode::HalfUp),
+            "truncate" => Ok(RoundingMode::Truncate),
+            _ => Err(EngineError::UnknownRoundingMode.into()),
+        }
+    }
+}
+
+impl RoundingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoundingMode::HalfEven => "half-even",
+            RoundingMode::HalfUp => "half-up",
+            RoundingMode::Truncate => "truncate",
+        }
+    }
+
+    pub fn round(&self, amount: Decimal, decimals: u32) -> Decimal {
+        let strategy = match self {
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::Truncate => RoundingStrategy::ToZero,
+        };
+        amount.round_dp_with_strategy(decimals, strategy)
+    }
+}
+
+// Reads a rate from a JSON number (or a string, as the decimals are serialized) without going through f64.
+pub fn decimal_from_value(value: &Value) -> Option<Decimal> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return None,
+    };
+    Decimal::from_str(&text).or_else(|_| Decimal::from_scientific(&text)).ok()
+}
+
+#[derive(Clone)]
+pub struct DerivedRate {
+    pub from: String,
+    pub to: String,
+    pub rate: Decimal,
+    // Currencies the rate was derived through, e.g. [USD, EUR, JPY] when the table has EUR as the base.
+    pub path: Vec<String>,
+    pub time_last_update_unix: i64,
+    pub time_last_update_utc: String,
+    pub time_next_update_unix: i64,
+}
+
+impl DerivedRate {
+    pub fn convert(&self, amount: Decimal) -> Result<Decimal> {
+        match amount.checked_mul(self.rate) {
+            Some(result) => Ok(result),
+            None => Err(EngineError::Overflow.into()),
+        }
+    }
+
+    pub fn display_rate(&self) -> Decimal {
+        self.rate.round_dp(RATE_DISPLAY_DECIMALS).normalize()
+    }
+
+    pub fn is_triangulated(&self) -> bool {
+        self.path.len() > 2
+    }
+
+    pub fn path_description(&self) -> String {
+        self.path.join(" -> ")
+    }
+}
+
+// Rate from the table's base currency to the given currency.
+fn base_rate(table: &ApiMultirateResponse, currency: &str) -> Option<Decimal> {
+    if currency == table.base_code {
+        return Some(Decimal::ONE);
+    }
+    table.conversion_rates.get(currency)
+}
+
+pub fn table_contains(table: &ApiMultirateResponse, currency: &str) -> bool {
+    currency.eq_ignore_ascii_case(&table.base_code) || table.conversion_rates.contains(currency)
+}
+
+pub fn derive_rate(table: &ApiMultirateResponse, from: &str, to: &str) -> Result<DerivedRate> {
+    let from = from.to_uppercase();
+    let to = to.to_uppercase();
+    let (from_rate, to_rate) = match (base_rate(table, &from), base_rate(table, &to)) {
+        (Some(from_rate), Some(to_rate)) => (from_rate, to_rate),
+        _ => return Err(RequestError::UnsupportedCurrency(None).into()),
+    };
+    let path = if from == to {
+        vec![from.clone()]
+    } else if from == table.base_code || to == table.base_code {
+        vec![from.clone(), to.clone()]
+    } else {
+        vec![from.clone(), table.base_code.clone(), to.clone()]
+    };
+    let rate = if from == to {
+        Decimal::ONE
+    } else {
+        match to_rate.checked_div(from_rate) {
+            Some(rate) => rate,
+            None => return Err(EngineError::Overflow.into()),
+        }
+    };
+    Ok(DerivedRate {
+        from,
+        to,
+        rate,
+        path,
+        time_last_update_unix: table.time_last_update_unix,
+        time_last_update_utc: table.time_last_update_utc.clone(),
+        time_next_update_unix: table.time_next_update_unix,
+    })
+}
+
+// A rate the provider returned for the pair itself.
+pub fn direct_rate(response: ApiRateResponse) -> DerivedRate {
+    DerivedRate {
+        path: vec![response.base_code.clone(), response.target_code.clone()],
+        from: response.base_code,
+        to: response.target_code,
+        rate: response.conversion_rate,
+        time_last_update_unix: response.time_last_update_unix,
+        time_last_update_utc: response.time_last_update_utc,
+        time_next_update_unix: response.time_next_update_unix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use rust_decimal::Decimal;
+    use super::{derive_rate, RoundingMode};
+    use crate::api::ApiMultirateResponse;
+    use crate::test_support::{decimal, LATEST_USD};
+
+
+    fn table() -> ApiMultirateResponse {
+        serde_json::from_str(LATEST_USD).unwrap()
+    }
+
+    #[test]
+    fn test_direct_rate_from_base() {
+        let rate = derive_rate(&table(), "USD", "EUR").unwrap();
+        assert_eq!(rate.rate, decimal("0.9181"));
+        assert_eq!(rate.path, vec!["USD", "EUR"]);
+        assert!(!rate.is_triangulated());
+        assert_eq!(rate.convert(decimal("100")).unwrap(), decimal("91.81"));
+    }
+
+    #[test]
+    fn test_inverse_rate_to_base() {
+        let rate = derive_rate(&table(), "eur", "usd").unwrap();
+        assert_eq!(rate.display_rate(), decimal("1.0892059688"));
+        assert_eq!(rate.path_description(), "EUR -> USD");
+    }
+
+    #[test]
+    fn test_cross_rate_through_base() {
+        let rate = derive_rate(&table(), "EUR", "JPY").unwrap();
+        assert_eq!(rate.display_rate(), decimal("162.2059688487"));
+        assert!(rate.is_triangulated());
+        assert_eq!(rate.path_description(), "EUR -> USD -> JPY");
+        assert_eq!(rate.time_last_update_unix, 1710460801);
+    }
+
+    #[test]
+    fn test_same_currency() {
+        let rate = derive_rate(&table(), "GBP", "GBP").unwrap();
+        assert_eq!(rate.rate, Decimal::ONE);
+        assert_eq!(rate.path, vec!["GBP"]);
+    }
+
+    #[test]
+    fn test_unsupported_currency() {
+        for (from, to) in [("UST", "EUR"), ("USD", "EUX"), ("UST", "EUX")] {
+            match derive_rate(&table(), from, to) {
+                Ok(_) => panic!("Expected an error, but got a rate."),
+                Err(e) => assert_eq!(e.to_string(), "Unsupported currency."),
+            }
+        }
+    }
+
+    #[test]
+    fn test_big_amount_keeps_precision() {
+        let rate = derive_rate(&table(), "USD", "EUR").unwrap();
+        let result = rate.convert(decimal("326235234543.32452362323")).unwrap();
+        assert_eq!(result, decimal("299516568834.226245138487463"));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let rate = derive_rate(&table(), "USD", "JPY").unwrap();
+        assert!(rate.convert(Decimal::MAX).is_err());
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        assert_eq!(RoundingMode::HalfEven.round(decimal("2.345"), 2), decimal("2.34"));
+        assert_eq!(RoundingMode::HalfEven.round(decimal("2.355"), 2), decimal("2.36"));
+        assert_eq!(RoundingMode::HalfUp.round(decimal("2.345"), 2), decimal("2.35"));
+        assert_eq!(RoundingMode::HalfUp.round(decimal("-2.345"), 2), decimal("-2.35"));
+        assert_eq!(RoundingMode::Truncate.round(decimal("2.349"), 2), decimal("2.34"));
+        assert_eq!(RoundingMode::Truncate.round(decimal("-2.349"), 2), decimal("-2.34"));
+        assert_eq!(RoundingMode::from_str("half-up").unwrap(), RoundingMode::HalfUp);
+        assert!(RoundingMode::from_str("ceiling").is_err());
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+// Arithmetic over amounts in different currencies, e.g. 100 USD + 250 EUR - 3000 JPY in GBP.
+// Every amount is converted to the currency after "in" (or "to") before it is added up,
+// without it the result is in the currency of the amounts, as long as they all have the same one.
+// Amounts can be multiplied or divided by numbers, e.g. (1200 EUR + 300 EUR) * 1.19 or 1200 EUR / 12,
+// and a percentage adds or subtracts a part of what's before it, e.g. 1200 EUR + 19%.
+
+#[derive(Error, Debug)]
+enum ExprError {
+    #[error("Unexpected {0} in the expression.")]
+    UnexpectedToken(String),
+    #[error("The expression is incomplete.")]
+    UnexpectedEnd,
+    #[error("Please end the expression with in and the currency of the result, e.g. 100 USD + 250 EUR in GBP.")]
+    MissingTarget,
+    #[error("Numbers can't be added to amounts in a currency, e.g. use 100 EUR + 5 USD or 100 EUR * 1.05.")]
+    MixedTerms,
+    #[error("Amounts in a currency can only be multiplied or divided by a number.")]
+    InvalidProduct,
+    #[error("The expression has no amounts in a currency.")]
+    NoAmounts,
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("The result is too large.")]
+    Overflow,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Word(String),
+    Symbol(char),
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(number) => number.to_string(),
+        Token::Word(word) => word.clone(),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.' || **c == '_') {
+                if c != '_' {
+                    number.push(c);
+                }
+                chars.next();
+            }
+            match Decimal::from_str(&number) {
+                Ok(number) => tokens.push(Token::Number(number)),
+                Err(_) => return Err(ExprError::UnexpectedToken(number).into()),
+            }
+        } else if c.is_alphabetic() {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphabetic()) {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word.to_uppercase()));
+        } else if "+-*/()%".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(ExprError::UnexpectedToken(format!("'{}'", c)).into());
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(Decimal),
+    Percent(Decimal),
+    Amount(Decimal, String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.tokens.get(self.position).cloned().ok_or(ExprError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn take_symbol(&mut self, symbols: &str) -> Option<char> {
+        match self.peek() {
+            Some(Token::Symbol(symbol)) if symbols.contains(*symbol) => {
+                let symbol = *symbol;
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr> {
+        let mut expr = self.term()?;
+        while let Some(operator) = self.take_symbol("+-") {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    // term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr> {
+        let mut expr = self.factor()?;
+        while let Some(operator) = self.take_symbol("*/") {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    // factor := '-' factor | '(' expression ')' | NUMBER ['%' | CURRENCY]
+    fn factor(&mut self) -> Result<Expr> {
+        match self.next()? {
+            Token::Symbol('-') => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Token::Symbol('(') => {
+                let expr = self.expression()?;
+                match self.next()? {
+                    Token::Symbol(')') => Ok(expr),
+                    token => Err(ExprError::UnexpectedToken(describe(&token)).into()),
+                }
+            }
+            Token::Number(number) => {
+                if self.take_symbol("%").is_some() {
+                    return Ok(Expr::Percent(number));
+                }
+                match self.peek().cloned() {
+                    Some(Token::Word(code)) => {
+                        self.position += 1;
+                        Ok(Expr::Amount(number, code))
+                    }
+                    _ => Ok(Expr::Number(number)),
+                }
+            }
+            token => Err(ExprError::UnexpectedToken(describe(&token)).into()),
+        }
+    }
+}
+
+// An amount as it's written in the expression.
+#[derive(Debug, PartialEq)]
+pub struct Term {
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+pub struct Expression {
+    expr: Expr,
+    pub target: String,
+}
+
+// Value of a part of the expression, amounts are already converted to the target currency.
+#[derive(Clone, Copy)]
+enum Value {
+    Number(Decimal),
+    Percent(Decimal),
+    Amount(Decimal),
+}
+
+fn checked(result: Option<Decimal>) -> Result<Decimal> {
+    result.ok_or_else(|| ExprError::Overflow.into())
+}
+
+// Percentages are parts of a hundred when they're used as numbers.
+fn number(value: Value) -> Option<Decimal> {
+    match value {
+        Value::Number(number) => Some(number),
+        Value::Percent(percent) => Some(percent / Decimal::ONE_HUNDRED),
+        Value::Amount(_) => None,
+    }
+}
+
+fn add(left: Value, right: Value, sign: Decimal) -> Result<Value> {
+    match (left, right) {
+        (Value::Amount(left), Value::Amount(right)) => Ok(Value::Amount(checked(left.checked_add(right * sign))?)),
+        (Value::Number(left), Value::Number(right)) => Ok(Value::Number(checked(left.checked_add(right * sign))?)),
+        // 1200 EUR + 19% is 1200 EUR * 1.19.
+        (Value::Amount(left), Value::Percent(percent)) => {
+            let factor = Decimal::ONE + sign * percent / Decimal::ONE_HUNDRED;
+            Ok(Value::Amount(checked(left.checked_mul(factor))?))
+        }
+        (Value::Number(left), Value::Percent(percent)) => {
+            let factor = Decimal::ONE + sign * percent / Decimal::ONE_HUNDRED;
+            Ok(Value::Number(checked(left.checked_mul(factor))?))
+        }
+        (Value::Percent(left), Value::Percent(right)) => Ok(Value::Percent(checked(left.checked_add(right * sign))?)),
+        _ => Err(ExprError::MixedTerms.into()),
+    }
+}
+
+fn multiply(left: Value, right: Value) -> Result<Value> {
+    match (left, right) {
+        (Value::Amount(_), Value::Amount(_)) => Err(ExprError::InvalidProduct.into()),
+        (Value::Amount(amount), number_value) | (number_value, Value::Amount(amount)) => {
+            let number = number(number_value).ok_or(ExprError::InvalidProduct)?;
+            Ok(Value::Amount(checked(amount.checked_mul(number))?))
+        }
+        (left, right) => {
+            let (left, right) = (number(left).ok_or(ExprError::InvalidProduct)?, number(right).ok_or(ExprError::InvalidProduct)?);
+            Ok(Value::Number(checked(left.checked_mul(right))?))
+        }
+    }
+}
+
+fn divide(left: Value, right: Value) -> Result<Value> {
+    let divisor = number(right).ok_or(ExprError::InvalidProduct)?;
+    if divisor.is_zero() {
+        return Err(ExprError::DivisionByZero.into());
+    }
+    match left {
+        Value::Amount(amount) => Ok(Value::Amount(checked(amount.checked_div(divisor))?)),
+        left => Ok(Value::Number(checked(number(left).ok_or(ExprError::InvalidProduct)?.checked_div(divisor))?)),
+    }
+}
+
+fn evaluate(expr: &Expr, rates: &HashMap<String, Decimal>) -> Result<Value> {
+    match expr {
+        Expr::Number(number) => Ok(Value::Number(*number)),
+        Expr::Percent(percent) => Ok(Value::Percent(*percent)),
+        Expr::Amount(amount, currency) => {
+            let rate = rates.get(currency).ok_or(ExprError::UnexpectedToken(currency.clone()))?;
+            Ok(Value::Amount(checked(amount.checked_mul(*rate))?))
+        }
+        Expr::Negate(expr) => match evaluate(expr, rates)? {
+            Value::Number(number) => Ok(Value::Number(-number)),
+            Value::Percent(percent) => Ok(Value::Percent(-percent)),
+            Value::Amount(amount) => Ok(Value::Amount(-amount)),
+        },
+        Expr::Binary(left, operator, right) => {
+            let (left, right) = (evaluate(left, rates)?, evaluate(right, rates)?);
+            match operator {
+                '+' => add(left, right, Decimal::ONE),
+                '-' => add(left, right, Decimal::NEGATIVE_ONE),
+                '*' => multiply(left, right),
+                _ => divide(left, right),
+            }
+        }
+    }
+}
+
+fn collect_terms(expr: &Expr, terms: &mut Vec<Term>) {
+    match expr {
+        Expr::Amount(amount, currency) => terms.push(Term { amount: *amount, currency: currency.clone() }),
+        Expr::Negate(expr) => collect_terms(expr, terms),
+        Expr::Binary(left, _, right) => {
+            collect_terms(left, terms);
+            collect_terms(right, terms);
+        }
+        Expr::Number(_) | Expr::Percent(_) => (),
+    }
+}
+
+impl Expression {
+    // Amounts in the order they appear, each of them has to be converted to the target currency.
+    pub fn terms(&self) -> Vec<Term> {
+        let mut terms = Vec::new();
+        collect_terms(&self.expr, &mut terms);
+        terms
+    }
+
+    // Result in the target currency, with the rates from every currency of the terms to it.
+    pub fn evaluate(&self, rates: &HashMap<String, Decimal>) -> Result<Decimal> {
+        match evaluate(&self.expr, rates)? {
+            Value::Amount(amount) => Ok(amount),
+            Value::Number(_) | Value::Percent(_) => Err(ExprError::NoAmounts.into()),
+        }
+    }
+}
+
+// Whether the input looks like an expression rather than a command, i.e. starts with a number or a parenthesis.
+pub fn is_expression(input: &str) -> bool {
+    input.trim_start().starts_with(|c: char| c.is_ascii_digit() || c == '(' || c == '.' || c == '-')
+}
+
+pub fn parse_expression(input: &str) -> Result<Expression> {
+    let mut tokens = tokenize(input)?;
+    let target = match tokens.as_slice() {
+        [.., Token::Word(keyword), Token::Word(target)] if keyword == "IN" || keyword == "TO" => Some(target.clone()),
+        _ => None,
+    };
+    if target.is_some() {
+        tokens.truncate(tokens.len() - 2);
+    }
+    let mut parser = Parser { tokens, position: 0 };
+    let expr = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        return Err(ExprError::UnexpectedToken(describe(token)).into());
+    }
+    let target = match target {
+        Some(target) => target,
+        None => {
+            // Without "in", the amounts have to agree on the currency of the result.
+            let mut terms = Vec::new();
+            collect_terms(&expr, &mut terms);
+            match terms.split_first() {
+                Some((first, rest)) if rest.iter().all(|term| term.currency == first.currency) => first.currency.clone(),
+                _ => return Err(ExprError::MissingTarget.into()),
+            }
+        }
+    };
+    Ok(Expression { expr, target })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use rust_decimal::Decimal;
+    use super::{is_expression, parse_expression, Term};
+    use crate::engine::RoundingMode;
+    use crate::test_support::decimal;
+
+    // Rates to GBP.
+    fn rates() -> HashMap<String, Decimal> {
+        HashMap::from([
+            ("GBP".to_string(), decimal("1")),
+            ("USD".to_string(), decimal("0.8")),
+            ("EUR".to_string(), decimal("0.85")),
+            ("JPY".to_string(), decimal("0.005")),
+        ])
+    }
+
+    fn evaluate(input: &str) -> String {
+        match parse_expression(input).and_then(|expression| expression.evaluate(&rates())) {
+            Ok(result) => result.normalize().to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        let expression = parse_expression("100 usd + 250 EUR - 3000 JPY in gbp").unwrap();
+        assert_eq!(expression.target, "GBP");
+        assert_eq!(expression.terms(), vec![
+            Term { amount: decimal("100"), currency: "USD".to_string() },
+            Term { amount: decimal("250"), currency: "EUR".to_string() },
+            Term { amount: decimal("3000"), currency: "JPY".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("100 USD + 250 EUR - 3000 JPY in GBP"), "277.5");
+        assert_eq!(evaluate("1200 EUR * 1.19 to GBP"), "1213.8");
+        assert_eq!(evaluate("2 * (100 USD + 100 EUR) / 4 in GBP"), "82.5");
+        assert_eq!(evaluate("1200 EUR + 19% in GBP"), "1213.8");
+        assert_eq!(evaluate("100 GBP - 10% in GBP"), "90");
+        assert_eq!(evaluate("100 GBP * 10% in GBP"), "10");
+        assert_eq!(evaluate("-(100 USD) + 1_000 GBP in GBP"), "920");
+    }
+
+    #[test]
+    fn test_target_from_terms() {
+        let expression = parse_expression("1200 EUR * 1.19").unwrap();
+        assert_eq!(expression.target, "EUR");
+        let result = expression.evaluate(&HashMap::from([("EUR".to_string(), Decimal::ONE)])).unwrap();
+        assert_eq!(format!("{} {}", RoundingMode::HalfEven.round(result, 2), expression.target), "1428.00 EUR");
+        assert_eq!(parse_expression("(100 usd + 50 USD) / 3").unwrap().target, "USD");
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert_eq!(evaluate("100 USD + 250 EUR"), "Please end the expression with in and the currency of the result, e.g. 100 USD + 250 EUR in GBP.");
+        assert_eq!(evaluate("100 USD + 5 in GBP"), "Numbers can't be added to amounts in a currency, e.g. use 100 EUR + 5 USD or 100 EUR * 1.05.");
+        assert_eq!(evaluate("100 USD * 2 EUR in GBP"), "Amounts in a currency can only be multiplied or divided by a number.");
+        assert_eq!(evaluate("100 * 2 in GBP"), "The expression has no amounts in a currency.");
+        assert_eq!(evaluate("100 USD / 0 in GBP"), "Division by zero.");
+        assert_eq!(evaluate("(100 USD + 2 EUR in GBP"), "The expression is incomplete.");
+        assert_eq!(evaluate("100 USD EUR in GBP"), "Unexpected EUR in the expression.");
+        assert_eq!(evaluate("100 USD + in GBP"), "The expression is incomplete.");
+        assert_eq!(evaluate("100 USD $ 5 in GBP"), "Unexpected '$' in the expression.");
+    }
+
+    #[test]
+    fn test_is_expression() {
+        assert!(is_expression("100 USD + 250 EUR in GBP"));
+        assert!(is_expression("(1 EUR + 1 USD) * 2 in JPY"));
+        assert!(!is_expression("convert USD EUR 100"));
+    }
+}
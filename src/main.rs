@@ -1,28 +1,72 @@
 use tokio::{task::spawn, signal::ctrl_c};
 use user_input::command_loop;
+use commands::new_command;
+use watch::stop_watch;
+use std::env;
 use std::process::exit;
 
+mod alerts;
 mod api;
+mod batch;
+mod cache;
 mod user_input;
 mod commands;
 mod config;
+mod currency;
+mod ecb;
+mod engine;
+mod expr;
+mod output;
+mod phrase;
+mod provider;
+mod rate_table;
+mod retry;
+mod store;
+mod usage;
+mod watch;
+#[cfg(test)]
+mod test_server;
+#[cfg(test)]
+mod test_support;
 
 #[tokio::main]
 async fn main() {
+    // With arguments, run a single command and exit with its status, e.g. currency-converter convert USD EUR 100
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Some((command, rest)) = args.split_first() {
+        let command = match command.as_str() {
+            "--help" | "-h" => "help",
+            command => command,
+        };
+        let rest = rest.iter().map(String::as_str).collect::<Vec<&str>>();
+        spawn(handle_ctrl_c());
+        let status = new_command(command, Some(rest)).await;
+        exit(status.exit_code());
+    }
+
     println!("Welcome to the Currency Converter!");
-    println!("This program uses www.exchangerate-api.com to get the latest exchange rates.");
+    println!("This program uses www.exchangerate-api.com or the European Central Bank to get the latest exchange rates.");
     println!("Type help for a list of commands.");
 
     spawn(command_loop());
+    handle_ctrl_c().await;
+}
 
-    match ctrl_c().await {
-        Ok(_) => {
-            println!("Exiting the program...");
-            exit(0);
-        }
-        Err(e) => {
-            println!("Error exiting program: {}", e);
-            exit(1);
+// Ctrl-C stops a running watch, otherwise it exits the program.
+async fn handle_ctrl_c() {
+    loop {
+        match ctrl_c().await {
+            Ok(_) => {
+                if stop_watch() {
+                    continue;
+                }
+                println!("Exiting the program...");
+                exit(0);
+            }
+            Err(e) => {
+                println!("Error exiting program: {}", e);
+                exit(1);
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::io::Write;
+use std::str::FromStr;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde_json::{json, Number, Value};
+use thiserror::Error;
+use crate::api::{ApiMultirateResponse, RequestError};
+use crate::currency::find_currency;
+use crate::engine::RATE_DISPLAY_DECIMALS;
+
+// Commands print prose by default. With --format json every result (and every error) is printed
+// as a single JSON object on one line, so it can be piped into tools like jq.
+// The table of all rates can also be exported as CSV or TSV, e.g. to be pasted into a spreadsheet.
+
+#[derive(Error, Debug)]
+enum OutputError {
+    #[error("Unknown output format. Available formats: text, json, csv, tsv.")]
+    UnknownFormat,
+    #[error("Error writing the rate table.")]
+    WriteError,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<OutputFormat> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(OutputError::UnknownFormat.into()),
+        }
+    }
+}
+
+impl OutputFormat {
+    // Field separator of the table formats.
+    pub fn delimiter(&self) -> Option<u8> {
+        match self {
+            OutputFormat::Csv => Some(b','),
+            OutputFormat::Tsv => Some(b'\t'),
+            OutputFormat::Text | OutputFormat::Json => None,
+        }
+    }
+}
+
+pub fn print_json(value: &Value) {
+    println!("{}", value);
+}
+
+// Decimals are serialized as strings by default, they are written as exact JSON numbers instead.
+pub fn decimal_json(decimal: Decimal) -> Value {
+    match Number::from_str(&decimal.to_string()) {
+        Ok(number) => Value::Number(number),
+        Err(_) => Value::String(decimal.to_string()),
+    }
+}
+
+// Writes one row per currency: code, name, rate, inverse rate, base and the time of the last update.
+pub fn write_rate_table<W: Write>(table: &ApiMultirateResponse, delimiter: u8, writer: W) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(writer);
+    let header = ["code", "name", "rate", "inverse_rate", "base", "time_last_update_utc"];
+    if writer.write_record(header).is_err() {
+        return Err(OutputError::WriteError.into());
+    }
+    let inverse_rates = table.conversion_rates.inverted();
+    for (code, rate) in table.conversion_rates.iter() {
+        let name = find_currency(code).map(|currency| currency.name).unwrap_or_default();
+        let inverse_rate = match inverse_rates.get(code) {
+            Some(inverse_rate) => inverse_rate.round_dp(RATE_DISPLAY_DECIMALS).normalize().to_string(),
+            None => String::new(),
+        };
+        let record = [code, name, &rate.to_string(), &inverse_rate, &table.base_code, &table.time_last_update_utc];
+        if writer.write_record(record).is_err() {
+            return Err(OutputError::WriteError.into());
+        }
+    }
+    match writer.flush() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(OutputError::WriteError.into()),
+    }
+}
+
+// Errors coming from the provider keep the kind of the request error, e.g. "quota-reached",
+// and the status and body of the API response when there was one.
+// Invalid commands or arguments are "usage" errors, everything else (config, network, cache) is "error".
+pub fn error_json(error: &anyhow::Error, usage: bool) -> Value {
+    let request_error = error.downcast_ref::<RequestError>();
+    let kind = match request_error {
+        Some(request_error) => request_error.kind(),
+        None if usage => "usage",
+        None => "error",
+    };
+    let mut error_json = json!({
+        "error": {
+            "kind": kind,
+            "message": error.to_string(),
+        }
+    });
+    if let Some(response) = request_error.and_then(RequestError::response) {
+        error_json["error"]["http_status"] = json!(response.status);
+        error_json["error"]["response_body"] = json!(response.body);
+    }
+    error_json
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use anyhow::anyhow;
+    use super::{error_json, write_rate_table, OutputFormat};
+    use crate::api::{ApiMultirateResponse, HttpResponse, RequestError};
+    use crate::test_support::LATEST_USD;
+
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!(OutputFormat::from_str("json").unwrap(), OutputFormat::Json);
+        assert_eq!(OutputFormat::from_str("text").unwrap(), OutputFormat::Text);
+        assert_eq!(OutputFormat::from_str("tsv").unwrap().delimiter(), Some(b'\t'));
+        assert!(OutputFormat::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn test_error_json() {
+        let error = error_json(&RequestError::QuotaReached(None).into(), false);
+        assert_eq!(error["error"]["kind"], "quota-reached");
+        assert_eq!(error["error"]["message"], "Quota reached.");
+        assert!(error["error"].get("http_status").is_none());
+        let response = HttpResponse { status: 429, body: r#"{"result":"error","error-type":"quota-reached"}"#.to_string() };
+        let error = error_json(&RequestError::QuotaReached(Some(response)).into(), false);
+        assert_eq!(error["error"]["http_status"], 429);
+        assert_eq!(error["error"]["response_body"], r#"{"result":"error","error-type":"quota-reached"}"#);
+        let error = error_json(&anyhow!("Unknown currency code: EUX."), true);
+        assert_eq!(error["error"]["kind"], "usage");
+        let error = error_json(&anyhow!("Error reading config."), false);
+        assert_eq!(error["error"]["kind"], "error");
+    }
+
+    fn rate_table(delimiter: u8) -> Vec<String> {
+        let table: ApiMultirateResponse = serde_json::from_str(LATEST_USD).unwrap();
+        let mut output = Vec::new();
+        write_rate_table(&table, delimiter, &mut output).unwrap();
+        String::from_utf8(output).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_csv_rate_table() {
+        let rows = rate_table(b',');
+        assert_eq!(rows[0], "code,name,rate,inverse_rate,base,time_last_update_utc");
+        assert!(rows.contains(&"EUR,Euro,0.9181,1.0892059688,USD,\"Fri, 15 Mar 2024 00:00:01 +0000\"".to_string()));
+        assert!(rows.contains(&"USD,US Dollar,1,1,USD,\"Fri, 15 Mar 2024 00:00:01 +0000\"".to_string()));
+        assert_eq!(rows.len(), 27);
+    }
+
+    #[test]
+    fn test_tsv_rate_table() {
+        let rows = rate_table(b'\t');
+        assert_eq!(rows[0], "code\tname\trate\tinverse_rate\tbase\ttime_last_update_utc");
+        assert!(rows.contains(&"JPY\tYen\t148.9213\t0.0067149562\tUSD\tFri, 15 Mar 2024 00:00:01 +0000".to_string()));
+    }
+}
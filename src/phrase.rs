@@ -0,0 +1,111 @@
+use std::str::FromStr;
+use rust_decimal::Decimal;
+use crate::currency::resolve_currency;
+
+// Free-form conversions typed instead of a command, e.g. 100 usd to eur, €50 in $, 50eur->chf or usd eur.
+// Currencies can be written as codes in any case or as symbols, and the amount can be written next to them.
+
+#[derive(Debug, PartialEq)]
+pub enum Phrase {
+    Convert { amount: Decimal, from: &'static str, to: &'static str },
+    Rate { from: &'static str, to: &'static str },
+}
+
+#[derive(Clone, Copy)]
+enum Word {
+    Amount(Decimal),
+    Currency(&'static str),
+    // to, in, into, as or =, between the currency converted from and the one converted to.
+    Connector,
+}
+
+fn is_number_char(c: char) -> bool {
+    c.is_ascii_digit() || c == '.'
+}
+
+// Splits e.g. €50 or 50eur into the amount and the currency.
+fn split_amount(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (index, c) in word.char_indices().skip(1) {
+        let previous = word[..index].chars().last().unwrap_or(c);
+        if is_number_char(previous) != is_number_char(c) {
+            parts.push(&word[start..index]);
+            start = index;
+        }
+    }
+    parts.push(&word[start..]);
+    parts
+}
+
+fn parse_word(word: &str) -> Option<Word> {
+    if ["to", "in", "into", "as", "="].iter().any(|connector| connector.eq_ignore_ascii_case(word)) {
+        return Some(Word::Connector);
+    }
+    if word.starts_with(is_number_char) {
+        return Decimal::from_str(word).ok().map(Word::Amount);
+    }
+    resolve_currency(word).map(|currency| Word::Currency(currency.code))
+}
+
+// Returns None when the input can't be read as a conversion, so it can be reported as an unknown command.
+pub fn parse_phrase(input: &str) -> Option<Phrase> {
+    let input = input.replace("->", " to ").replace("=>", " to ").replace('→', " to ");
+    let mut words = Vec::new();
+    for word in input.split_whitespace() {
+        for part in split_amount(word) {
+            words.push(parse_word(part)?);
+        }
+    }
+    // The connector is optional, but there can't be more than one.
+    let connectors = words.iter().filter(|word| matches!(word, Word::Connector)).count();
+    let words = match (connectors, words.as_slice()) {
+        (0, _) => words,
+        (1, [.., Word::Connector, Word::Currency(_)]) => words.into_iter().filter(|word| !matches!(word, Word::Connector)).collect(),
+        _ => return None,
+    };
+    match words.as_slice() {
+        [Word::Amount(amount), Word::Currency(from), Word::Currency(to)] | [Word::Currency(from), Word::Amount(amount), Word::Currency(to)] => {
+            Some(Phrase::Convert { amount: *amount, from, to })
+        }
+        [Word::Currency(from), Word::Currency(to)] => Some(Phrase::Rate { from, to }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use rust_decimal::Decimal;
+    use super::{parse_phrase, Phrase};
+
+    fn convert(amount: &str, from: &'static str, to: &'static str) -> Option<Phrase> {
+        Some(Phrase::Convert { amount: Decimal::from_str(amount).unwrap(), from, to })
+    }
+
+    #[test]
+    fn test_parse_conversions() {
+        assert_eq!(parse_phrase("100 usd to eur"), convert("100", "USD", "EUR"));
+        assert_eq!(parse_phrase("100 USD EUR"), convert("100", "USD", "EUR"));
+        assert_eq!(parse_phrase("€50 in $"), convert("50", "EUR", "USD"));
+        assert_eq!(parse_phrase("50eur->chf"), convert("50", "EUR", "CHF"));
+        assert_eq!(parse_phrase("12.5 £ => zł"), convert("12.5", "GBP", "PLN"));
+        assert_eq!(parse_phrase("jpy 1000 as usd"), convert("1000", "JPY", "USD"));
+    }
+
+    #[test]
+    fn test_parse_rates() {
+        assert_eq!(parse_phrase("usd eur"), Some(Phrase::Rate { from: "USD", to: "EUR" }));
+        assert_eq!(parse_phrase("€ to $"), Some(Phrase::Rate { from: "EUR", to: "USD" }));
+    }
+
+    #[test]
+    fn test_no_interpretation() {
+        assert_eq!(parse_phrase("hello"), None);
+        assert_eq!(parse_phrase("100 usd"), None);
+        assert_eq!(parse_phrase("100 usd to eur to gbp"), None);
+        assert_eq!(parse_phrase("usd to 100 eur"), None);
+        assert_eq!(parse_phrase("100 kr to eur"), None);
+        assert_eq!(parse_phrase("1.2.3 usd eur"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use thiserror::Error;
+use crate::api::{ApiEnrichedResponse, ApiMultirateResponse, ApiRateResponse, ExchangeRateApi};
+use crate::config::read_config;
+use crate::ecb::Ecb;
+use crate::engine::derive_rate;
+
+// Every source of exchange rates implements this trait, so the commands don't need to know
+// which service the rates are coming from. The response structs follow the ExchangeRate-API format,
+// other providers convert their data into the same shape.
+
+pub const DEFAULT_PROVIDER: &str = "exchangerate-api";
+// Used when no provider is configured and there is no API key for the default one.
+pub const KEYLESS_PROVIDER: &str = "ecb";
+pub const PROVIDERS: [&str; 2] = ["exchangerate-api", "ecb"];
+
+#[derive(Error, Debug)]
+enum ProviderError {
+    #[error("Unknown provider. Available providers: {0}.")]
+    UnknownProvider(String),
+    #[error("{0} has no currency details.")]
+    NoCurrencyDetails(String),
+}
+
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Base currency of the table that is fetched when rates between other currencies are derived locally.
+    fn reference_currency(&self) -> &'static str {
+        "USD"
+    }
+
+    async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse>;
+
+    async fn pair(&self, from: &str, to: &str) -> Result<ApiRateResponse>;
+
+    // Table of all rates published on the given date.
+    async fn history(&self, base_currency: &str, date: NaiveDate) -> Result<ApiMultirateResponse>;
+
+    // Returns pairs of currency code and currency name.
+    async fn supported_codes(&self) -> Result<Vec<(String, String)>>;
+
+    // The pair rate with details about the target currency, only some providers (and plans) have them.
+    async fn enriched(&self, _from: &str, _to: &str) -> Result<ApiEnrichedResponse> {
+        Err(ProviderError::NoCurrencyDetails(self.name().to_string()).into())
+    }
+}
+
+pub fn check_provider_name(name: &str) -> Result<()> {
+    match PROVIDERS.contains(&name) {
+        true => Ok(()),
+        false => Err(ProviderError::UnknownProvider(PROVIDERS.join(", ")).into()),
+    }
+}
+
+pub fn provider_by_name(name: &str) -> Result<Box<dyn RateProvider>> {
+    match name {
+        "exchangerate-api" => Ok(Box::new(ExchangeRateApi::from_config()?)),
+        "ecb" => Ok(Box::new(Ecb::from_config()?)),
+        _ => Err(ProviderError::UnknownProvider(PROVIDERS.join(", ")).into()),
+    }
+}
+
+// Name of the configured provider, without creating it (which needs an API key for ExchangeRate-API).
+pub fn provider_name_from_config() -> Result<String> {
+    let config = read_config()?;
+    let name = match (config.provider, config.api_key) {
+        (Some(name), _) => name,
+        (None, Some(_)) => DEFAULT_PROVIDER.to_string(),
+        (None, None) => KEYLESS_PROVIDER.to_string(),
+    };
+    Ok(name)
+}
+
+pub fn provider_from_config() -> Result<Box<dyn RateProvider>> {
+    provider_by_name(&provider_name_from_config()?)
+}
+
+// Builds a pair response from a table of all rates, the table's base doesn't have to be one of the currencies.
+pub fn pair_from_table(table: ApiMultirateResponse, from: &str, to: &str) -> Result<ApiRateResponse> {
+    let derived = derive_rate(&table, from, to)?;
+    Ok(ApiRateResponse {
+        result: table.result,
+        documentation: table.documentation,
+        terms_of_use: table.terms_of_use,
+        time_last_update_unix: table.time_last_update_unix,
+        time_last_update_utc: table.time_last_update_utc,
+        time_next_update_unix: table.time_next_update_unix,
+        time_next_update_utc: table.time_next_update_utc,
+        base_code: derived.from,
+        target_code: derived.to,
+        conversion_rate: derived.rate,
+    })
+}
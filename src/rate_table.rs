@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+use crate::engine::decimal_from_value;
+use crate::output::decimal_json;
+
+// Rates of a table, from its base currency to every other currency, ordered by code.
+// Every code is three uppercase letters and every rate is positive. In the JSON of the providers
+// and the cache it is an object of code to rate, entries that aren't valid are left out when it's read.
+
+#[derive(Error, Debug)]
+enum RateTableError {
+    #[error("Invalid currency code in the rate table: {0}.")]
+    InvalidCode(String),
+    #[error("Invalid rate for {0} in the rate table.")]
+    InvalidRate(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateTable {
+    rates: BTreeMap<String, Decimal>,
+}
+
+fn is_valid_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|char| char.is_ascii_alphabetic())
+}
+
+impl RateTable {
+    pub fn new() -> RateTable {
+        RateTable::default()
+    }
+
+    pub fn insert(&mut self, code: &str, rate: Decimal) -> Result<()> {
+        if !is_valid_code(code) {
+            return Err(RateTableError::InvalidCode(code.to_string()).into());
+        }
+        if !rate.is_sign_positive() || rate.is_zero() {
+            return Err(RateTableError::InvalidRate(code.to_uppercase()).into());
+        }
+        self.rates.insert(code.to_uppercase(), rate);
+        Ok(())
+    }
+
+    // Rate from the base currency to the given one.
+    pub fn get(&self, code: &str) -> Option<Decimal> {
+        self.rates.get(&code.to_uppercase()).copied()
+    }
+
+    // Rate from the given currency to the base one.
+    pub fn inverse(&self, code: &str) -> Option<Decimal> {
+        self.get(code).and_then(|rate| Decimal::ONE.checked_div(rate))
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        self.rates.contains_key(&code.to_uppercase())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.rates.keys().map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Decimal)> {
+        self.rates.iter().map(|(code, rate)| (code.as_str(), *rate))
+    }
+
+    // The rates from every currency to the base one.
+    pub fn inverted(&self) -> RateTable {
+        let rates = self.rates.keys().filter_map(|code| Some((code.clone(), self.inverse(code)?))).collect();
+        RateTable { rates }
+    }
+
+    // The same rates with another currency of the table as the base, None when it's not in the table.
+    pub fn rebased(&self, base_code: &str) -> Option<RateTable> {
+        let base_rate = self.get(base_code)?;
+        let mut rates = BTreeMap::new();
+        for (code, rate) in &self.rates {
+            let rate = if code.eq_ignore_ascii_case(base_code) { Decimal::ONE } else { rate.checked_div(base_rate)?.normalize() };
+            rates.insert(code.clone(), rate);
+        }
+        Some(RateTable { rates })
+    }
+
+    // Only the rates of the currencies the predicate keeps.
+    pub fn filter<F: Fn(&str, Decimal) -> bool>(&self, predicate: F) -> RateTable {
+        let rates = self.rates.iter().filter(|(code, rate)| predicate(code, **rate)).map(|(code, rate)| (code.clone(), *rate)).collect();
+        RateTable { rates }
+    }
+}
+
+impl Serialize for RateTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.rates.iter().map(|(code, rate)| (code, decimal_json(*rate))))
+    }
+}
+
+impl<'de> Deserialize<'de> for RateTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<RateTable, D::Error> {
+        let entries = BTreeMap::<String, Value>::deserialize(deserializer)?;
+        let mut table = RateTable::new();
+        for (code, rate) in entries {
+            if let Some(rate) = decimal_from_value(&rate) {
+                // An invalid entry only leaves the currency out of the table.
+                let _ = table.insert(&code, rate);
+            }
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use serde_json::json;
+    use super::RateTable;
+    use crate::test_support::decimal;
+
+    fn table() -> RateTable {
+        serde_json::from_value(json!({ "USD": 1, "EUR": 0.9181, "JPY": "148.9213", "GBP": 0.7849 })).unwrap()
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table = table();
+        assert_eq!(table.get("EUR"), Some(decimal("0.9181")));
+        assert_eq!(table.get("jpy"), Some(decimal("148.9213")));
+        assert_eq!(table.get("CHF"), None);
+        assert_eq!(table.inverse("EUR").unwrap().round_dp(10), decimal("1.0892059688"));
+        assert_eq!(table.codes().collect::<Vec<&str>>(), vec!["EUR", "GBP", "JPY", "USD"]);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let table: RateTable = serde_json::from_value(json!({ "USD": 1, "EURO": 0.9, "GBP": 0, "JPY": -1, "CHF": "abc", "PLN": 3.94 })).unwrap();
+        assert_eq!(table.codes().collect::<Vec<&str>>(), vec!["PLN", "USD"]);
+        assert!(serde_json::from_value::<RateTable>(json!([1, 2])).is_err());
+        let mut table = RateTable::new();
+        assert!(table.insert("E1R", Decimal::ONE).is_err());
+        assert!(table.insert("EUR", Decimal::ZERO).is_err());
+        assert!(table.insert("eur", Decimal::ONE).is_ok());
+        assert!(table.contains("EUR"));
+    }
+
+    #[test]
+    fn test_rebase_and_invert() {
+        let rebased = table().rebased("EUR").unwrap();
+        assert_eq!(rebased.get("EUR"), Some(Decimal::ONE));
+        assert_eq!(rebased.get("USD").unwrap().round_dp(10), decimal("1.0892059688"));
+        assert_eq!(rebased.get("JPY").unwrap().round_dp(10), decimal("162.2059688487"));
+        assert!(rebased.rebased("CHF").is_none());
+        let inverted = table().inverted();
+        assert_eq!(inverted.get("USD"), Some(Decimal::ONE));
+        assert_eq!(inverted.get("GBP"), table().inverse("GBP"));
+    }
+
+    #[test]
+    fn test_filter_and_serialize() {
+        let table = table().filter(|code, rate| code != "USD" && rate < Decimal::ONE);
+        assert_eq!(table.codes().count(), 2);
+        assert_eq!(serde_json::to_string(&table).unwrap(), r#"{"EUR":0.9181,"GBP":0.7849}"#);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use anyhow::Result;
+use thiserror::Error;
+use crate::api::RequestError;
+use crate::config::Config;
+
+// Requests to the API that fail for a reason that may go away (a dropped connection, a timeout
+// or a 5xx answer without an error type) are tried again, waiting twice as long before every retry.
+// Errors the API reports itself, like invalid-key or unsupported-code, are never retried.
+// The wait is shortened by a random part (the jitter), so many clients don't retry at the same moment.
+
+#[derive(Error, Debug)]
+enum RetryError {
+    #[error("Invalid retry statuses. Please provide HTTP status codes separated by commas, e.g. 500,502,503,504.")]
+    InvalidStatuses,
+}
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_JITTER: f64 = 0.5;
+pub const DEFAULT_RETRY_STATUSES: [u16; 4] = [500, 502, 503, 504];
+// No single wait is longer than this, however many attempts are configured.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    // Including the first request, 1 means no retries.
+    pub max_attempts: u32,
+    // Wait before the first retry, doubled for every next one.
+    pub base_delay: Duration,
+    // Fraction of the wait that is random, from 0 (always the full wait) to 1.
+    pub jitter: f64,
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            jitter: DEFAULT_JITTER,
+            retry_statuses: DEFAULT_RETRY_STATUSES.to_vec(),
+        }
+    }
+}
+
+pub fn parse_statuses(statuses: &str) -> Result<Vec<u16>> {
+    let mut parsed = Vec::new();
+    for status in statuses.split(',') {
+        match status.trim().parse::<u16>() {
+            Ok(status) if (100..600).contains(&status) => parsed.push(status),
+            _ => return Err(RetryError::InvalidStatuses.into()),
+        }
+    }
+    Ok(parsed)
+}
+
+// A number from 0 to 1, random enough to spread the retries.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl RetryPolicy {
+    // The policy set in the config, with the defaults for anything missing or invalid.
+    pub fn from_config(config: &Config) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts.unwrap_or(default.max_attempts).max(1),
+            base_delay: config.retry_base_delay_ms.map(Duration::from_millis).unwrap_or(default.base_delay),
+            jitter: config.retry_jitter.filter(|jitter| (0.0..=1.0).contains(jitter)).unwrap_or(default.jitter),
+            retry_statuses: config.retry_statuses.as_deref().and_then(|statuses| parse_statuses(statuses).ok()).unwrap_or(default.retry_statuses),
+        }
+    }
+
+    // Only used by tests, which don't want to wait.
+    #[cfg(test)]
+    pub fn without_delay(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, base_delay: Duration::ZERO, ..RetryPolicy::default() }
+    }
+
+    // Whether a request that failed with this error should be tried again.
+    pub fn should_retry(&self, error: &anyhow::Error) -> bool {
+        if let Some(request_error) = error.downcast_ref::<RequestError>() {
+            return match request_error {
+                // Only answers the API didn't explain, e.g. a proxy's 503 page. A body that can't be decoded
+                // comes with a successful status (any other one is an UnknownError), so it isn't retried.
+                RequestError::UnknownError(Some(response)) => self.retry_statuses.contains(&response.status),
+                _ => false,
+            };
+        }
+        match error.downcast_ref::<reqwest::Error>() {
+            Some(error) => error.is_timeout() || error.is_connect() || error.is_request() || error.is_body(),
+            None => false,
+        }
+    }
+
+    // Wait before the given retry (1 for the first one), the random part is taken from the fraction.
+    pub fn delay(&self, retry: u32, random_fraction: f64) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1))).min(MAX_DELAY);
+        exponential.mul_f64(1.0 - self.jitter * random_fraction)
+    }
+
+    pub fn next_delay(&self, retry: u32) -> Duration {
+        self.delay(retry, random_fraction())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use anyhow::anyhow;
+    use super::{parse_statuses, RetryPolicy};
+    use crate::api::{HttpResponse, RequestError};
+    use crate::config::Config;
+
+    fn response(status: u16) -> Option<HttpResponse> {
+        Some(HttpResponse { status, body: String::new() })
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy { jitter: 0.0, ..RetryPolicy::default() };
+        assert_eq!(policy.delay(1, 0.7), Duration::from_millis(500));
+        assert_eq!(policy.delay(2, 0.7), Duration::from_millis(1000));
+        assert_eq!(policy.delay(3, 0.7), Duration::from_millis(2000));
+        assert_eq!(policy.delay(20, 0.7), Duration::from_secs(30));
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(2, 0.0), Duration::from_millis(1000));
+        assert_eq!(policy.delay(2, 0.5), Duration::from_millis(750));
+        assert!(policy.next_delay(2) <= Duration::from_millis(1000));
+        assert!(policy.next_delay(2) >= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&RequestError::UnknownError(response(503)).into()));
+        assert!(!policy.should_retry(&RequestError::InvalidResponse(response(200)).into()));
+        assert!(!policy.should_retry(&RequestError::UnknownError(response(418)).into()));
+        // Errors the API explains are permanent, whatever the status.
+        assert!(!policy.should_retry(&RequestError::InvalidApiKey(response(500)).into()));
+        assert!(!policy.should_retry(&RequestError::UnsupportedCurrency(response(503)).into()));
+        assert!(!policy.should_retry(&RequestError::QuotaReached(response(429)).into()));
+        assert!(!policy.should_retry(&RequestError::UnknownError(None).into()));
+        assert!(!policy.should_retry(&anyhow!("No API key set.")));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config {
+            retry_max_attempts: Some(0),
+            retry_base_delay_ms: Some(200),
+            retry_jitter: Some(1.5),
+            retry_statuses: Some("429, 503".to_string()),
+            ..Config::default()
+        };
+        let policy = RetryPolicy::from_config(&config);
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.base_delay, Duration::from_millis(200));
+        assert_eq!(policy.jitter, 0.5);
+        assert_eq!(policy.retry_statuses, vec![429, 503]);
+        assert_eq!(RetryPolicy::from_config(&Config::default()), RetryPolicy::default());
+        assert!(parse_statuses("500,abc").is_err());
+        assert!(parse_statuses("700").is_err());
+    }
+}
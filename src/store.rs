@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use rust_decimal::Decimal;
+use thiserror::Error;
+use crate::api::ApiMultirateResponse;
+use crate::config::get_data_dir;
+
+// Every table of rates that is fetched is also stored in a SQLite database in the data directory,
+// so we keep our own history of rates no matter which provider or plan is used.
+// A table is stored once per provider, base currency and update time. Rates are stored as text to keep them exact.
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS snapshots (
+        id INTEGER PRIMARY KEY,
+        provider TEXT NOT NULL,
+        base_code TEXT NOT NULL,
+        time_last_update_unix INTEGER NOT NULL,
+        time_last_update_utc TEXT NOT NULL,
+        UNIQUE (provider, base_code, time_last_update_unix)
+    );
+    CREATE TABLE IF NOT EXISTS rates (
+        snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+        code TEXT NOT NULL,
+        rate TEXT NOT NULL,
+        PRIMARY KEY (snapshot_id, code)
+    );
+";
+
+#[derive(Error, Debug)]
+enum StoreError {
+    #[error("Invalid rate in the rate store.")]
+    InvalidRate,
+}
+
+pub struct Snapshot {
+    pub provider: String,
+    pub base_code: String,
+    pub time_last_update_unix: i64,
+    pub time_last_update_utc: String,
+    pub rate_count: usize,
+}
+
+// Rate of a pair in a single stored table.
+pub struct PairRate {
+    pub time_last_update_unix: i64,
+    pub time_last_update_utc: String,
+    pub rate: Decimal,
+}
+
+pub struct RateStore {
+    connection: Connection,
+}
+
+impl RateStore {
+    pub fn open(path: &Path) -> Result<RateStore> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        RateStore::from_connection(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<RateStore> {
+        RateStore::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<RateStore> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(RateStore { connection })
+    }
+
+    pub fn from_config() -> Result<RateStore> {
+        RateStore::open(&store_path()?)
+    }
+
+    // Returns false when the table was already stored.
+    pub fn save(&mut self, provider: &str, table: &ApiMultirateResponse) -> Result<bool> {
+        let transaction = self.connection.transaction()?;
+        let inserted = transaction.execute(
+            "INSERT OR IGNORE INTO snapshots (provider, base_code, time_last_update_unix, time_last_update_utc) VALUES (?1, ?2, ?3, ?4)",
+            params![provider, table.base_code, table.time_last_update_unix, table.time_last_update_utc],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        let snapshot_id = transaction.last_insert_rowid();
+        {
+            let mut statement = transaction.prepare("INSERT INTO rates (snapshot_id, code, rate) VALUES (?1, ?2, ?3)")?;
+            for (code, rate) in table.conversion_rates.iter() {
+                statement.execute(params![snapshot_id, code, rate.to_string()])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let mut statement = self.connection.prepare(
+            "SELECT provider, base_code, time_last_update_unix, time_last_update_utc,
+                (SELECT COUNT(*) FROM rates WHERE snapshot_id = snapshots.id)
+            FROM snapshots ORDER BY time_last_update_unix, provider, base_code",
+        )?;
+        let snapshots = statement.query_map([], |row| {
+            Ok(Snapshot {
+                provider: row.get(0)?,
+                base_code: row.get(1)?,
+                time_last_update_unix: row.get(2)?,
+                time_last_update_utc: row.get(3)?,
+                rate_count: row.get(4)?,
+            })
+        })?;
+        Ok(snapshots.collect::<rusqlite::Result<Vec<Snapshot>>>()?)
+    }
+
+    // Rate from one currency to another in every stored table containing both, updated between start and end (inclusive).
+    // When several tables have the same update time (e.g. with different bases), the first one is used.
+    pub fn pair_rates(&self, from: &str, to: &str, start: i64, end: i64) -> Result<Vec<PairRate>> {
+        let mut statement = self.connection.prepare(
+            "SELECT time_last_update_unix, time_last_update_utc,
+                (SELECT rate FROM rates WHERE snapshot_id = snapshots.id AND code = ?1),
+                (SELECT rate FROM rates WHERE snapshot_id = snapshots.id AND code = ?2)
+            FROM snapshots
+            WHERE time_last_update_unix BETWEEN ?3 AND ?4
+            ORDER BY time_last_update_unix, id",
+        )?;
+        let rows = statement.query_map(params![from.to_uppercase(), to.to_uppercase(), start, end], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+        })?;
+        let mut pair_rates: Vec<PairRate> = Vec::new();
+        for row in rows {
+            let (time_last_update_unix, time_last_update_utc, from_rate, to_rate) = row?;
+            let (from_rate, to_rate) = match (from_rate, to_rate) {
+                (Some(from_rate), Some(to_rate)) => (parse_rate(&from_rate)?, parse_rate(&to_rate)?),
+                _ => continue,
+            };
+            if pair_rates.last().is_some_and(|last| last.time_last_update_unix == time_last_update_unix) {
+                continue;
+            }
+            let rate = match to_rate.checked_div(from_rate) {
+                Some(rate) => rate,
+                None => return Err(StoreError::InvalidRate.into()),
+            };
+            pair_rates.push(PairRate { time_last_update_unix, time_last_update_utc, rate });
+        }
+        Ok(pair_rates)
+    }
+}
+
+fn parse_rate(rate: &str) -> Result<Decimal> {
+    match Decimal::from_str(rate) {
+        Ok(rate) if !rate.is_zero() => Ok(rate),
+        _ => Err(StoreError::InvalidRate.into()),
+    }
+}
+
+pub fn store_path() -> Result<PathBuf> {
+    Ok(PathBuf::from(get_data_dir()?).join("rates.sqlite3"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateStore;
+    use crate::api::{ApiHistoryResponse, ApiMultirateResponse};
+    use crate::test_support::{decimal, HISTORY_USD, LATEST_USD};
+
+
+    fn tables() -> (ApiMultirateResponse, ApiMultirateResponse) {
+        let latest = serde_json::from_str(LATEST_USD).unwrap();
+        let history = serde_json::from_str::<ApiHistoryResponse>(HISTORY_USD).unwrap().into_table().unwrap();
+        (latest, history)
+    }
+
+    #[test]
+    fn test_save_deduplicated() {
+        let mut store = RateStore::open_in_memory().unwrap();
+        let (latest, history) = tables();
+        assert!(store.save("exchangerate-api", &latest).unwrap());
+        assert!(!store.save("exchangerate-api", &latest).unwrap());
+        assert!(store.save("exchangerate-api", &history).unwrap());
+        assert!(store.save("ecb", &history).unwrap());
+        let snapshots = store.snapshots().unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].provider, "ecb");
+        assert_eq!(snapshots[0].time_last_update_utc, "Thu, 14 Mar 2024 00:00:00 +0000");
+        assert_eq!(snapshots[0].rate_count, 9);
+        assert_eq!(snapshots[2].base_code, "USD");
+        assert_eq!(snapshots[2].rate_count, 26);
+    }
+
+    #[test]
+    fn test_pair_rates() {
+        let mut store = RateStore::open_in_memory().unwrap();
+        let (latest, history) = tables();
+        store.save("exchangerate-api", &latest).unwrap();
+        store.save("exchangerate-api", &history).unwrap();
+        store.save("ecb", &history).unwrap();
+        let rates = store.pair_rates("eur", "PLN", 0, i64::MAX).unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].rate, decimal("3.9212") / decimal("0.9153"));
+        assert_eq!(rates[1].rate, decimal("3.9417") / decimal("0.9181"));
+        assert_eq!(rates[1].time_last_update_unix, 1710460801);
+        let rates = store.pair_rates("USD", "EUR", 1710460801, 1710460801).unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].rate, decimal("0.9181"));
+        // BRL is only in the latest table.
+        assert_eq!(store.pair_rates("USD", "BRL", 0, i64::MAX).unwrap().len(), 1);
+        assert!(store.pair_rates("USD", "XAU", 0, i64::MAX).unwrap().is_empty());
+    }
+}
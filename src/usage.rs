@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use fd_lock::RwLock;
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::api::{ApiQuotaResponse, ExchangeRateApi};
+use crate::cache::unix_now;
+use crate::config::{get_data_dir, read_config};
+
+// Every request made to a provider is counted per day in the data directory, so we know how much of the quota we use.
+// The last answer of ExchangeRate-API's quota endpoint is kept as well, and the requests made since then
+// are added to it to estimate the usage until the quota is checked again. The interactive mode warns
+// once for every threshold (a percentage of the quota) the estimate crosses, until the quota refreshes.
+
+#[derive(Error, Debug)]
+enum UsageError {
+    #[error("Error reading the request counts.")]
+    ReadError,
+    #[error("Error writing the request counts.")]
+    WriteError,
+    #[error("Invalid thresholds. Please provide percentages of the quota separated by commas, e.g. 80,90,100.")]
+    InvalidThresholds,
+}
+
+// Only ExchangeRate-API has a request quota.
+pub const QUOTA_PROVIDER: &str = "exchangerate-api";
+// The quota is checked again when the interactive mode starts and the last check is older than this.
+pub const QUOTA_MAX_AGE: i64 = 24 * 60 * 60;
+
+pub const DEFAULT_THRESHOLDS: [u32; 3] = [80, 90, 100];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuotaSnapshot {
+    pub plan_quota: u64,
+    pub requests_remaining: u64,
+    pub refresh_day_of_month: u32,
+    pub time_fetched_unix: i64,
+    // Requests made after the snapshot was taken.
+    pub requests_since: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Usage {
+    // Requests per day (YYYY-MM-DD) and provider.
+    #[serde(default)]
+    pub requests: BTreeMap<String, BTreeMap<String, u64>>,
+    // Last quota of every provider that has one.
+    #[serde(default)]
+    pub quotas: BTreeMap<String, QuotaSnapshot>,
+    // Highest threshold already warned about per provider.
+    #[serde(default)]
+    pub warned_thresholds: BTreeMap<String, u32>,
+}
+
+// Estimated usage of the quota now.
+#[derive(Debug, PartialEq)]
+pub struct QuotaEstimate {
+    pub plan_quota: u64,
+    pub requests_used: u64,
+    pub refresh_date: NaiveDate,
+}
+
+fn percent_of(requests_used: u64, plan_quota: u64) -> u64 {
+    match plan_quota {
+        0 => 100,
+        plan_quota => requests_used * 100 / plan_quota,
+    }
+}
+
+impl QuotaEstimate {
+    pub fn percent_used(&self) -> u64 {
+        percent_of(self.requests_used, self.plan_quota)
+    }
+}
+
+fn date_of(time_unix: i64) -> NaiveDate {
+    DateTime::<Utc>::from_timestamp(time_unix, 0).unwrap_or_default().date_naive()
+}
+
+// First date after the given one on the refresh day, or on the last day of months that are too short for it.
+pub fn next_refresh_date(after: NaiveDate, refresh_day: u32) -> NaiveDate {
+    let first_of_month = after.with_day(1).unwrap_or(after);
+    (0..=2)
+        .filter_map(|months| first_of_month.checked_add_months(Months::new(months)))
+        .filter_map(|month| {
+            let last_day = month.checked_add_months(Months::new(1))?.checked_sub_days(Days::new(1))?.day();
+            month.with_day(refresh_day.clamp(1, last_day))
+        })
+        .find(|date| *date > after)
+        .unwrap_or(after)
+}
+
+impl Usage {
+    pub fn requests_on(&self, provider: &str, date: NaiveDate) -> u64 {
+        self.requests.get(&date.to_string()).and_then(|counts| counts.get(provider)).copied().unwrap_or(0)
+    }
+
+    // Requests of the given number of days up to and including the date.
+    pub fn requests_in_days(&self, provider: &str, until: NaiveDate, days: u64) -> u64 {
+        (0..days).filter_map(|day| until.checked_sub_days(Days::new(day))).map(|date| self.requests_on(provider, date)).sum()
+    }
+
+    // None when the quota wasn't checked yet, or it has refreshed since.
+    pub fn estimate(&self, provider: &str, today: NaiveDate) -> Option<QuotaEstimate> {
+        let quota = self.quotas.get(provider)?;
+        let refresh_date = next_refresh_date(date_of(quota.time_fetched_unix), quota.refresh_day_of_month);
+        if today >= refresh_date {
+            return None;
+        }
+        let requests_used = quota.plan_quota.saturating_sub(quota.requests_remaining) + quota.requests_since;
+        Some(QuotaEstimate { plan_quota: quota.plan_quota, requests_used, refresh_date })
+    }
+}
+
+pub fn usage_path() -> Result<PathBuf> {
+    Ok(PathBuf::from(get_data_dir()?).join("usage.json"))
+}
+
+pub fn read_usage(path: &Path) -> Result<Usage> {
+    if fs::metadata(path).is_err() {
+        return Ok(Usage::default());
+    }
+    let usage_json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(_) => return Err(UsageError::ReadError.into()),
+    };
+    match serde_json::from_str(&usage_json) {
+        Ok(usage) => Ok(usage),
+        Err(_) => Err(UsageError::ReadError.into()),
+    }
+}
+
+pub fn write_usage(path: &Path, usage: &Usage) -> Result<()> {
+    let usage_json = match serde_json::to_string_pretty(usage) {
+        Ok(json) => json,
+        Err(_) => return Err(UsageError::WriteError.into()),
+    };
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return Err(UsageError::WriteError.into());
+        }
+    }
+    // Written next to it and renamed, so the file is never read half-written.
+    let temp_path = path.with_extension("json.tmp");
+    match fs::write(&temp_path, usage_json).and_then(|_| fs::rename(&temp_path, path)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(UsageError::WriteError.into()),
+    }
+}
+
+// Reads the usage, changes it and writes it back, with no other change of the file in between.
+// Requests are counted from concurrent tasks (e.g. the startup requests of the REPL) and from other
+// running instances of the program, so the change is made under an advisory lock of usage.json.lock,
+// which every update takes, otherwise one of two counts could be lost.
+fn update_usage<T, F: FnOnce(&mut Usage) -> T>(path: &Path, update: F) -> Result<T> {
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return Err(UsageError::WriteError.into());
+        }
+    }
+    // A separate file, usage.json itself is replaced when it's written.
+    let lock_file = match File::create(path.with_extension("json.lock")) {
+        Ok(file) => file,
+        Err(_) => return Err(UsageError::WriteError.into()),
+    };
+    let mut lock = RwLock::new(lock_file);
+    let _guard = match lock.write() {
+        Ok(guard) => guard,
+        Err(_) => return Err(UsageError::WriteError.into()),
+    };
+    let mut usage = read_usage(path)?;
+    let result = update(&mut usage);
+    write_usage(path, &usage)?;
+    Ok(result)
+}
+
+pub fn count_request(path: &Path, provider: &str) -> Result<()> {
+    update_usage(path, |usage| {
+        *usage.requests.entry(today().to_string()).or_default().entry(provider.to_string()).or_insert(0) += 1;
+        if let Some(quota) = usage.quotas.get_mut(provider) {
+            quota.requests_since += 1;
+        }
+    })
+}
+
+pub fn save_quota(path: &Path, provider: &str, quota: &ApiQuotaResponse) -> Result<QuotaSnapshot> {
+    let snapshot = QuotaSnapshot {
+        plan_quota: quota.plan_quota,
+        requests_remaining: quota.requests_remaining,
+        refresh_day_of_month: quota.refresh_day_of_month,
+        time_fetched_unix: unix_now(),
+        requests_since: 0,
+    };
+    // The thresholds are warned about again when the usage went down, i.e. after a refresh.
+    let percent_used = percent_of(snapshot.plan_quota.saturating_sub(snapshot.requests_remaining), snapshot.plan_quota);
+    update_usage(path, |usage| {
+        usage.warned_thresholds.retain(|warned_provider, threshold| warned_provider != provider || u64::from(*threshold) <= percent_used);
+        usage.quotas.insert(provider.to_string(), snapshot.clone());
+    })?;
+    Ok(snapshot)
+}
+
+// Checks the quota of ExchangeRate-API again and keeps it.
+pub async fn refresh_quota(path: &Path) -> Result<QuotaSnapshot> {
+    let quota = ExchangeRateApi::from_config()?.quota().await?;
+    save_quota(path, QUOTA_PROVIDER, &quota)
+}
+
+pub fn parse_thresholds(thresholds: &str) -> Result<Vec<u32>> {
+    let mut parsed = Vec::new();
+    for threshold in thresholds.split(',') {
+        match threshold.trim().trim_end_matches('%').parse::<u32>() {
+            Ok(threshold) if threshold > 0 => parsed.push(threshold),
+            _ => return Err(UsageError::InvalidThresholds.into()),
+        }
+    }
+    parsed.sort_unstable();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+// The configured thresholds, or the default ones when they are missing or invalid.
+pub fn warning_thresholds() -> Vec<u32> {
+    read_config()
+        .ok()
+        .and_then(|config| config.quota_warning_thresholds)
+        .and_then(|thresholds| parse_thresholds(&thresholds).ok())
+        .unwrap_or(DEFAULT_THRESHOLDS.to_vec())
+}
+
+pub fn today() -> NaiveDate {
+    date_of(unix_now())
+}
+
+// Takes the warning for the provider from the stored usage, see take_quota_warning.
+pub fn check_quota_warning(path: &Path) -> Result<Option<QuotaEstimate>> {
+    let thresholds = warning_thresholds();
+    let warning = update_usage(path, |usage| take_quota_warning(usage, QUOTA_PROVIDER, &thresholds, today()))?;
+    Ok(warning.map(|(_, estimate)| estimate))
+}
+
+// Highest threshold crossed by the estimated usage that wasn't warned about yet, it's marked as warned.
+pub fn take_quota_warning(usage: &mut Usage, provider: &str, thresholds: &[u32], today: NaiveDate) -> Option<(u32, QuotaEstimate)> {
+    let estimate = match usage.estimate(provider, today) {
+        Some(estimate) => estimate,
+        None => {
+            usage.warned_thresholds.remove(provider);
+            return None;
+        }
+    };
+    let warned = usage.warned_thresholds.get(provider).copied().unwrap_or(0);
+    let crossed = thresholds
+        .iter()
+        .copied()
+        .filter(|threshold| *threshold > warned && u64::from(*threshold) <= estimate.percent_used())
+        .max()?;
+    usage.warned_thresholds.insert(provider.to_string(), crossed);
+    Some((crossed, estimate))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use chrono::NaiveDate;
+    use super::{count_request, next_refresh_date, parse_thresholds, read_usage, take_quota_warning, today, QuotaEstimate, QuotaSnapshot, Usage};
+    use crate::test_support::temp_path;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // Checked on 2024-03-14, with 1500 requests a month refreshing on the 17th.
+    fn usage(requests_remaining: u64, requests_since: u64) -> Usage {
+        let snapshot = QuotaSnapshot {
+            plan_quota: 1500,
+            requests_remaining,
+            refresh_day_of_month: 17,
+            time_fetched_unix: 1710417600,
+            requests_since,
+        };
+        Usage { quotas: BTreeMap::from([("exchangerate-api".to_string(), snapshot)]), ..Usage::default() }
+    }
+
+    #[test]
+    fn test_next_refresh_date() {
+        assert_eq!(next_refresh_date(date(2024, 3, 14), 17), date(2024, 3, 17));
+        assert_eq!(next_refresh_date(date(2024, 3, 17), 17), date(2024, 4, 17));
+        assert_eq!(next_refresh_date(date(2024, 12, 20), 17), date(2025, 1, 17));
+        assert_eq!(next_refresh_date(date(2024, 1, 31), 31), date(2024, 2, 29));
+        assert_eq!(next_refresh_date(date(2024, 2, 29), 31), date(2024, 3, 31));
+    }
+
+    #[test]
+    fn test_estimate() {
+        let usage = usage(300, 15);
+        let estimate = usage.estimate("exchangerate-api", date(2024, 3, 15)).unwrap();
+        assert_eq!(estimate, QuotaEstimate { plan_quota: 1500, requests_used: 1215, refresh_date: date(2024, 3, 17) });
+        assert_eq!(estimate.percent_used(), 81);
+        assert!(usage.estimate("exchangerate-api", date(2024, 3, 17)).is_none());
+        assert!(usage.estimate("ecb", date(2024, 3, 15)).is_none());
+    }
+
+    #[test]
+    fn test_warned_once_per_threshold() {
+        let today = date(2024, 3, 15);
+        let mut usage = usage(300, 0);
+        assert_eq!(take_quota_warning(&mut usage, "exchangerate-api", &[80, 90], today).map(|(threshold, _)| threshold), Some(80));
+        assert!(take_quota_warning(&mut usage, "exchangerate-api", &[80, 90], today).is_none());
+        usage.quotas.get_mut("exchangerate-api").unwrap().requests_since = 200;
+        assert_eq!(take_quota_warning(&mut usage, "exchangerate-api", &[80, 90], today).map(|(threshold, _)| threshold), Some(90));
+        // After the refresh there is nothing to warn about until the quota is checked again.
+        assert!(take_quota_warning(&mut usage, "exchangerate-api", &[80, 90], date(2024, 3, 18)).is_none());
+        assert!(usage.warned_thresholds.is_empty());
+    }
+
+    #[test]
+    fn test_requests_in_days() {
+        let mut usage = Usage::default();
+        usage.requests.insert("2024-03-14".to_string(), BTreeMap::from([("ecb".to_string(), 2), ("exchangerate-api".to_string(), 5)]));
+        usage.requests.insert("2024-03-10".to_string(), BTreeMap::from([("exchangerate-api".to_string(), 3)]));
+        assert_eq!(usage.requests_on("exchangerate-api", date(2024, 3, 14)), 5);
+        assert_eq!(usage.requests_in_days("exchangerate-api", date(2024, 3, 14), 5), 8);
+        assert_eq!(usage.requests_in_days("exchangerate-api", date(2024, 3, 14), 4), 5);
+    }
+
+    #[test]
+    fn test_concurrent_counts() {
+        let path = temp_path("usage");
+        let threads = (0..8).map(|_| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    count_request(&path, "ecb").unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let usage = read_usage(&path).unwrap();
+        assert_eq!(usage.requests[&today().to_string()]["ecb"], 80);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_extension("json.lock"));
+    }
+
+    #[test]
+    fn test_parse_thresholds() {
+        assert_eq!(parse_thresholds("90, 75%,90").unwrap(), vec![75, 90]);
+        assert!(parse_thresholds("0").is_err());
+        assert!(parse_thresholds("high").is_err());
+    }
+}
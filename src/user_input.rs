@@ -1,20 +1,208 @@
-use std::io::stdin;
-use crate::commands::new_command;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use anyhow::Result;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use tokio::task::spawn_blocking;
+use crate::cache::unix_now;
+use crate::commands::{new_command, quota_warning_message, COMMAND_NAMES};
+use crate::config::get_data_dir;
+use crate::currency::CURRENCIES;
+use crate::expr::is_expression;
+use crate::phrase::parse_phrase;
+use crate::cache::cached_provider_from_config;
+use crate::provider::{provider_from_config, RateProvider};
+use crate::usage::{check_quota_warning, read_usage, refresh_quota, usage_path, QUOTA_MAX_AGE, QUOTA_PROVIDER};
+
+// The interactive mode reads commands with a line editor: arrow keys go through the history, which is kept
+// in the data directory between sessions, Ctrl-R searches it, and Tab completes commands and currency codes.
+
+const PROMPT: &str = "> ";
+
+struct InputHelper {
+    // The built-in currency codes until the provider's supported codes are loaded.
+    currency_codes: Arc<RwLock<Vec<String>>>,
+}
+
+// Start of the word being completed and the candidates for it. The first word is a command,
+// the others are currency codes.
+fn complete_word(line: &str, position: usize, currency_codes: &[String]) -> (usize, Vec<String>) {
+    let line = &line[..position];
+    let start = line.rfind(char::is_whitespace).map(|index| index + 1).unwrap_or(0);
+    let word = &line[start..];
+    let candidates = if line[..start].trim().is_empty() {
+        COMMAND_NAMES.iter().filter(|name| name.starts_with(&word.to_lowercase())).map(|name| name.to_string()).collect()
+    } else {
+        currency_codes.iter().filter(|code| code.starts_with(&word.to_uppercase())).cloned().collect()
+    };
+    (start, candidates)
+}
+
+impl Completer for InputHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, position: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let codes = match self.currency_codes.read() {
+            Ok(codes) => codes.clone(),
+            Err(_) => Vec::new(),
+        };
+        let (start, candidates) = complete_word(line, position, &codes);
+        let pairs = candidates.into_iter().map(|candidate| Pair { display: candidate.clone(), replacement: candidate }).collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for InputHelper {
+    type Hint = String;
+}
+
+impl Highlighter for InputHelper {}
+
+impl Validator for InputHelper {}
+
+impl Helper for InputHelper {}
+
+fn history_path() -> Result<PathBuf> {
+    Ok(PathBuf::from(get_data_dir()?).join("history.txt"))
+}
+
+// Without a network connection or an API key, the built-in codes are completed instead.
+fn load_supported_codes(currency_codes: Arc<RwLock<Vec<String>>>) {
+    tokio::spawn(async move {
+        let codes = match cached_provider_from_config(false) {
+            Ok(provider) => provider.supported_codes().await,
+            Err(e) => Err(e),
+        };
+        if let (Ok(codes), Ok(mut currency_codes)) = (codes, currency_codes.write()) {
+            if !codes.is_empty() {
+                *currency_codes = codes.into_iter().map(|(code, _)| code).collect();
+            }
+        }
+    });
+}
+
+// Checks the quota when the last check is too old for the estimate, only when the provider has one.
+fn refresh_stale_quota() {
+    tokio::spawn(async {
+        let has_quota = provider_from_config().is_ok_and(|provider| provider.name() == QUOTA_PROVIDER);
+        let path = match usage_path() {
+            Ok(path) if has_quota => path,
+            _ => return,
+        };
+        let stale = match read_usage(&path) {
+            Ok(usage) => match usage.quotas.get(QUOTA_PROVIDER) {
+                Some(quota) => unix_now() - quota.time_fetched_unix > QUOTA_MAX_AGE,
+                None => true,
+            },
+            Err(_) => false,
+        };
+        if stale {
+            let _ = refresh_quota(&path).await;
+        }
+    });
+}
+
+fn print_quota_warning() {
+    if let Ok(path) = usage_path() {
+        if let Ok(Some(estimate)) = check_quota_warning(&path) {
+            println!("{}", quota_warning_message(&estimate));
+        }
+    }
+}
+
+async fn run_line(input: &str) {
+    let split = input.split_whitespace().collect::<Vec<&str>>();
+    if split.is_empty() {
+        return;
+    }
+    // Expressions like 100 USD + 250 EUR in GBP are calculated without a command,
+    // simple conversions like 100 usd to eur are left to the command, which reads them as phrases.
+    if is_expression(input) && parse_phrase(input).is_none() {
+        new_command("calc", Some(split)).await;
+        return;
+    }
+    let command = split[0];
+    let args = if split.len() > 1 {
+        Some(split[1..].to_vec())
+    } else {
+        None
+    };
+    new_command(command, args).await;
+}
 
 pub async fn command_loop() {
-    loop {
-        let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
-        let split = input.trim().split_whitespace().collect::<Vec<&str>>();
-        if split.is_empty() {
-            continue;
+    let mut editor = match Editor::<InputHelper, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            println!("Error starting the interactive mode: {}", e);
+            std::process::exit(1);
         }
-        let command = split[0];
-        let args = if split.len() > 1 {
-            Some(split[1..].to_vec())
-        } else {
-            None
+    };
+    let currency_codes = Arc::new(RwLock::new(CURRENCIES.iter().map(|currency| currency.code.to_string()).collect()));
+    load_supported_codes(currency_codes.clone());
+    refresh_stale_quota();
+    editor.set_helper(Some(InputHelper { currency_codes }));
+    let history_path = history_path().ok();
+    if let Some(path) = &history_path {
+        // There is no history file before the first session.
+        let _ = editor.load_history(path);
+    }
+    loop {
+        // The editor blocks while it waits for input, so it runs outside of the async runtime.
+        let (returned_editor, line) = match spawn_blocking(move || {
+            let line = editor.readline(PROMPT);
+            (editor, line)
+        }).await {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Error reading input: {}", e);
+                std::process::exit(1);
+            }
+        };
+        editor = returned_editor;
+        let input = match line {
+            Ok(input) => input,
+            // Ctrl-C or Ctrl-D while typing exits, like Ctrl-C did before the line editor.
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                println!("Exiting the program...");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                println!("Error reading input: {}", e);
+                std::process::exit(1);
+            }
         };
-        new_command(command, args).await;
+        if !input.trim().is_empty() {
+            let _ = editor.add_history_entry(input.trim());
+            // Saved before the command runs, as exit doesn't return.
+            if let Some(path) = &history_path {
+                let _ = std::fs::create_dir_all(path.parent().unwrap_or(path));
+                let _ = editor.save_history(path);
+            }
+        }
+        run_line(&input).await;
+        print_quota_warning();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::complete_word;
+
+    #[test]
+    fn test_complete_word() {
+        let codes = ["EUR", "ETB", "USD"].map(String::from);
+        assert_eq!(complete_word("con", 3, &codes), (0, vec!["convert".to_string()]));
+        assert_eq!(complete_word("r", 1, &codes), (0, vec!["rates".to_string(), "rate".to_string()]));
+        assert_eq!(complete_word("rate usd e", 10, &codes), (9, vec!["EUR".to_string(), "ETB".to_string()]));
+        assert_eq!(complete_word("rate u", 6, &codes), (5, vec!["USD".to_string()]));
+        // Only the text before the cursor is completed.
+        assert_eq!(complete_word("convert EUR", 3, &codes), (0, vec!["convert".to_string()]));
+        assert!(complete_word("rate x", 6, &codes).1.is_empty());
     }
-}
\ No newline at end of file
+}
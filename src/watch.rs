@@ -0,0 +1,214 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use crate::cache::{unix_now, CachedProvider};
+use crate::engine::DerivedRate;
+
+// Watches the rate of a pair until it is stopped with Ctrl-C.
+// The rate is read through the cache, so the provider is only asked again after its next update time.
+// Only one watch runs at a time, the command loop waits for it to finish.
+
+#[derive(Error, Debug)]
+enum WatchError {
+    #[error("Invalid interval. Please provide a number of seconds, optionally with s, m or h (e.g. 30, 5m, 1h).")]
+    InvalidInterval,
+}
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Notified when the running watch should stop.
+static ACTIVE_WATCH: Mutex<Option<Arc<Notify>>> = Mutex::new(None);
+
+pub struct RateChange {
+    pub rate: DerivedRate,
+    // Difference from the first rate of the watch.
+    pub change: Decimal,
+    pub change_percent: Decimal,
+}
+
+impl RateChange {
+    fn new(rate: DerivedRate, start_rate: Decimal) -> RateChange {
+        let change = rate.rate - start_rate;
+        let change_percent = match (change * Decimal::ONE_HUNDRED).checked_div(start_rate) {
+            Some(percent) => percent,
+            None => Decimal::ZERO,
+        };
+        RateChange { rate, change, change_percent }
+    }
+}
+
+pub fn parse_interval(interval: &str) -> Result<Duration> {
+    let (number, multiplier) = match interval.char_indices().last() {
+        Some((index, 's')) => (&interval[..index], 1),
+        Some((index, 'm')) => (&interval[..index], 60),
+        Some((index, 'h')) => (&interval[..index], 60 * 60),
+        _ => (interval, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => Ok(Duration::from_secs(number * multiplier)),
+        _ => Err(WatchError::InvalidInterval.into()),
+    }
+}
+
+// Stops the running watch, returns false when there is none.
+pub fn stop_watch() -> bool {
+    let active_watch = match ACTIVE_WATCH.lock() {
+        Ok(mut active_watch) => active_watch.take(),
+        Err(_) => None,
+    };
+    match active_watch {
+        Some(stop) => {
+            // The permit is kept until the watch waits for it, so it also stops during a request.
+            stop.notify_one();
+            true
+        }
+        None => false,
+    }
+}
+
+// Waits for the interval, or longer when the rates won't be updated before then.
+fn wait_time(rate: &DerivedRate, interval: Duration) -> Duration {
+    let until_next_update = (rate.time_next_update_unix - unix_now()).max(0) as u64;
+    interval.max(Duration::from_secs(until_next_update))
+}
+
+// Reports the first rate and every change until the watch is stopped. Errors while polling are reported
+// and the watch goes on, only an error getting the first rate ends it.
+pub async fn watch_rate<F: FnMut(Result<RateChange>)>(provider: &CachedProvider, from: &str, to: &str, interval: Duration, mut report: F) -> Result<()> {
+    let stop = Arc::new(Notify::new());
+    if let Ok(mut active_watch) = ACTIVE_WATCH.lock() {
+        *active_watch = Some(stop.clone());
+    }
+    let result = async {
+        let first_rate = provider.derived_rate(from, to).await?;
+        let start_rate = first_rate.rate;
+        let mut last_rate = first_rate.rate;
+        let mut wait = wait_time(&first_rate, interval);
+        report(Ok(RateChange::new(first_rate, start_rate)));
+        loop {
+            tokio::select! {
+                _ = stop.notified() => return Ok(()),
+                _ = sleep(wait) => (),
+            }
+            tokio::select! {
+                _ = stop.notified() => return Ok(()),
+                rate = provider.derived_rate(from, to) => match rate {
+                    Ok(rate) => {
+                        wait = wait_time(&rate, interval);
+                        if rate.rate != last_rate {
+                            last_rate = rate.rate;
+                            report(Ok(RateChange::new(rate, start_rate)));
+                        }
+                    }
+                    Err(e) => {
+                        wait = interval;
+                        report(Err(e));
+                    }
+                },
+            }
+        }
+    }.await;
+    if let Ok(mut active_watch) = ACTIVE_WATCH.lock() {
+        if active_watch.as_ref().is_some_and(|active| Arc::ptr_eq(active, &stop)) {
+            *active_watch = None;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use serde_json::json;
+    use super::{parse_interval, stop_watch, watch_rate};
+    use crate::api::{ApiMultirateResponse, ApiRateResponse, RequestError};
+    use crate::cache::{unix_now, CachedProvider};
+    use crate::provider::RateProvider;
+    use crate::test_support::temp_path;
+
+    // The EUR rate goes up by 0.01 every second request, and the table is always expired.
+    struct ChangingProvider {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl RateProvider for ChangingProvider {
+        fn name(&self) -> &'static str {
+            "changing"
+        }
+
+        async fn latest(&self, base_currency: &str) -> Result<ApiMultirateResponse> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst);
+            let rate = Decimal::from_str("0.90").unwrap() + Decimal::new((calls / 2) as i64, 2);
+            Ok(ApiMultirateResponse {
+                result: "success".to_string(),
+                documentation: String::new(),
+                terms_of_use: String::new(),
+                time_last_update_unix: unix_now(),
+                time_last_update_utc: String::new(),
+                time_next_update_unix: unix_now() - 1,
+                time_next_update_utc: String::new(),
+                base_code: base_currency.to_string(),
+                conversion_rates: serde_json::from_value(json!({ "USD": 1, "EUR": rate.to_string() }))?,
+            })
+        }
+
+        async fn pair(&self, _from: &str, _to: &str) -> Result<ApiRateResponse> {
+            Err(RequestError::UnsupportedCurrency(None).into())
+        }
+
+        async fn history(&self, _base_currency: &str, _date: NaiveDate) -> Result<ApiMultirateResponse> {
+            Err(RequestError::NoDataAvailable(None).into())
+        }
+
+        async fn supported_codes(&self) -> Result<Vec<(String, String)>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_interval("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_interval("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_interval("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("soon").is_err());
+        assert!(parse_interval("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_changes_until_stopped() {
+        let cache_dir = temp_path("watch");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = CachedProvider::new(Box::new(ChangingProvider { calls: calls.clone() }), cache_dir.clone(), false);
+        let stopper = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(stop_watch());
+        });
+        let mut changes = Vec::new();
+        watch_rate(&provider, "USD", "EUR", Duration::from_millis(10), |change| changes.push(change.unwrap())).await.unwrap();
+        stopper.await.unwrap();
+        assert!(!stop_watch());
+        // Every rate is reported once, even though it's requested twice.
+        assert!(changes.len() >= 2);
+        assert!(calls.load(Ordering::SeqCst) >= changes.len() * 2 - 1);
+        assert_eq!(changes[0].rate.rate, Decimal::from_str("0.90").unwrap());
+        assert_eq!(changes[0].change, Decimal::ZERO);
+        assert_eq!(changes[1].rate.rate, Decimal::from_str("0.91").unwrap());
+        assert_eq!(changes[1].change, Decimal::from_str("0.01").unwrap());
+        assert_eq!(changes[1].change_percent.round_dp(4), Decimal::from_str("1.1111").unwrap());
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+}
@@ -0,0 +1,40 @@
+// Runs the program with ExchangeRate-API as the provider but no API key, in its own config, cache and data directories.
+#![cfg(unix)]
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+use common::home_dir;
+
+// Nothing listens on the base URL, a request would fail with a connection error instead of the missing key.
+fn run(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_currency-converter"))
+        .args(args)
+        .env_clear()
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("PROVIDER", "exchangerate-api")
+        .env("API_BASE_URL", "http://127.0.0.1:9")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_missing_key_fails_before_requests() {
+    let home = home_dir("rate");
+    let output = run(&home, &["rate", "USD", "EUR"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No API key set."));
+}
+
+#[test]
+fn test_provider_without_key() {
+    let home = home_dir("provider");
+    let output = run(&home, &["provider", "view"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Provider: exchangerate-api"));
+    assert!(run(&home, &["provider", "set", "exchangerate-api"]).status.success());
+}
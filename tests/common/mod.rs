@@ -0,0 +1,12 @@
+// Helpers shared by the integration tests, which run the built program.
+
+use std::path::PathBuf;
+
+// A home directory for one run of the program, with its own config, cache and data directories.
+// Anything an earlier test run left there is removed.
+pub fn home_dir(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("currency-converter-home-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).unwrap();
+    home
+}
@@ -0,0 +1,84 @@
+// Runs the program against a local stand-in of ExchangeRate-API, in its own config, cache and data directories,
+// and checks that alerts triggered while a command fetches rates don't end up in its JSON or CSV output.
+#![cfg(unix)]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+use common::home_dir;
+use std::thread;
+
+const LATEST_USD: &str = include_str!("fixtures/exchangerate-api/latest-USD.json");
+const CODES: &str = include_str!("fixtures/exchangerate-api/codes.json");
+
+// Answers every request with the recorded table or the recorded list of codes.
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&chunk[..read]),
+                }
+            }
+            let body = if String::from_utf8_lossy(&request).contains("/codes ") { CODES } else { LATEST_USD };
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    format!("http://{}", address)
+}
+
+fn run(home: &Path, base_url: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_currency-converter"))
+        .args(args)
+        .env_clear()
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("PROVIDER", "exchangerate-api")
+        .env("API_KEY", "test-key")
+        .env("API_BASE_URL", base_url)
+        .output()
+        .unwrap()
+}
+
+// The recorded USD/EUR rate is 0.9181, so the alert triggers with the first fetched table.
+fn add_alert(home: &Path, base_url: &str) {
+    let output = run(home, base_url, &["alert", "add", "USD/EUR", "below", "0.95"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn test_alert_not_in_json_output() {
+    let base_url = start_server();
+    let home = home_dir("json");
+    add_alert(&home, &base_url);
+    let output = run(&home, &base_url, &["rate", "USD", "EUR", "--format", "json"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rate: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(rate["rate"].to_string(), "0.9181");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Alert 1: USD/EUR is below 0.95 (0.9181)."));
+}
+
+#[test]
+fn test_alert_not_in_csv_output() {
+    let base_url = start_server();
+    let home = home_dir("csv");
+    add_alert(&home, &base_url);
+    let output = run(&home, &base_url, &["all", "USD", "--format", "csv"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("code,name,rate,inverse_rate,base,time_last_update_utc\n"));
+    assert!(stdout.lines().skip(1).all(|line| line.split(',').count() >= 6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Alert 1"));
+}